@@ -0,0 +1,43 @@
+/* C API for the Q evaluator; see qcapi/src/lib.rs for details. */
+#ifndef QCAPI_H
+#define QCAPI_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct QSession QSession;
+typedef struct QValue QValue;
+
+typedef enum QKind {
+    Q_ERROR = 0,
+    Q_BOOL = 1,
+    Q_INT = 2,
+    Q_FLOAT = 3,
+    Q_RATIONAL = 4,
+    Q_COMPLEX = 5,
+    /* Int outside 64-bit range; read its digits with q_value_string */
+    Q_BIG_INT = 6,
+} QKind;
+
+QSession *q_session_new(void);
+void q_session_set_exact(QSession *session, bool exact);
+void q_session_free(QSession *session);
+
+/* Never returns null; failures have kind Q_ERROR. */
+QValue *q_eval(QSession *session, const char *source);
+QKind q_value_kind(const QValue *value);
+int64_t q_value_int(const QValue *value);
+double q_value_float(const QValue *value);
+/* Owned by the value; valid until q_value_free. */
+const char *q_value_string(const QValue *value);
+void q_value_free(QValue *value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* QCAPI_H */
@@ -0,0 +1,250 @@
+//! C API for embedding the Q evaluator in non-Rust hosts.
+//!
+//! A host creates a session, evaluates source strings in it and inspects
+//! each result through an opaque value handle:
+//!
+//! ```c
+//! QSession *s = q_session_new();
+//! QValue *v = q_eval(s, "1+2");
+//! if (q_value_kind(v) == Q_INT) printf("%lld\n", q_value_int(v));
+//! q_value_free(v);
+//! q_session_free(s);
+//! ```
+//!
+//! Every handle returned by this API must be released with its `_free`
+//! function. See `include/qcapi.h` for the C declarations.
+
+use std::ffi::{CStr, CString, c_char};
+
+use bumpalo::Bump;
+use num_traits::ToPrimitive;
+use qparser::ast::Expr;
+use qparser::{Config, eval_in};
+
+/// Evaluation session: settings plus an arena reused across evaluations.
+pub struct QSession {
+    arena: Bump,
+    config: Config,
+}
+
+/// Kind of an evaluation result.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QKind {
+    /// Parse or evaluation error; the text is the message
+    Error = 0,
+    Bool = 1,
+    Int = 2,
+    Float = 3,
+    Rational = 4,
+    Complex = 5,
+    /// Int outside 64-bit range; the text holds its digits
+    BigInt = 6,
+}
+
+/// Result of one evaluation, owned by the host until `q_value_free`.
+pub struct QValue {
+    kind: QKind,
+    /// Integer value of an int or boolean (1 or 0)
+    int: i64,
+    /// Numeric value as a float; the real part of a complex number
+    float: f64,
+    /// Display form of the atom, or the error message
+    text: CString,
+}
+
+impl QValue {
+    fn new(result: Result<Expr<'_>, String>) -> QValue {
+        let (kind, int, float, text) = match result {
+            Ok(atom) => {
+                let text = atom.to_string();
+                match atom {
+                    Expr::Bool(b) => (QKind::Bool, b as i64, b as i64 as f64, text),
+                    Expr::Int(i) => (QKind::Int, i, i as f64, text),
+                    Expr::Float(x) => (QKind::Float, 0, x, text),
+                    Expr::Rational(r) => (
+                        QKind::Rational,
+                        0,
+                        *r.numer() as f64 / *r.denom() as f64,
+                        text,
+                    ),
+                    Expr::Complex(c) => (QKind::Complex, 0, c.re, text),
+                    Expr::BigInt(i) => (QKind::BigInt, 0, i.to_f64().unwrap_or(f64::NAN), text),
+                    _ => unreachable!("evaluation yields an atom"),
+                }
+            }
+            Err(message) => (QKind::Error, 0, f64::NAN, message),
+        };
+        QValue {
+            kind,
+            int,
+            float,
+            // The source was a C string, so messages quoting it have no NUL
+            text: CString::new(text).unwrap(),
+        }
+    }
+}
+
+/// Create a session with the default settings.
+#[unsafe(no_mangle)]
+pub extern "C" fn q_session_new() -> *mut QSession {
+    Box::into_raw(Box::new(QSession {
+        arena: Bump::new(),
+        config: Config::default(),
+    }))
+}
+
+/// Turn exact mode (int division yields rationals) on or off.
+///
+/// # Safety
+///
+/// `session` must come from [`q_session_new`] and not yet be freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn q_session_set_exact(session: *mut QSession, exact: bool) {
+    let session = unsafe { &mut *session };
+    session.config.exact = exact;
+}
+
+/// Free a session. Null is ignored.
+///
+/// # Safety
+///
+/// `session` must be null or come from [`q_session_new`], and must not be
+/// used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn q_session_free(session: *mut QSession) {
+    if !session.is_null() {
+        drop(unsafe { Box::from_raw(session) });
+    }
+}
+
+/// Evaluate a NUL-terminated UTF-8 expression. Always returns a value;
+/// failures have kind [`QKind::Error`] and the message as their text.
+///
+/// # Safety
+///
+/// `session` must come from [`q_session_new`] and not yet be freed, and
+/// `source` must point to a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn q_eval(session: *mut QSession, source: *const c_char) -> *mut QValue {
+    let session = unsafe { &mut *session };
+    let source = unsafe { CStr::from_ptr(source) };
+    let value = match source.to_str() {
+        Ok(source) => QValue::new(eval_in(&session.arena, source, &session.config)),
+        Err(_) => QValue::new(Err("Source is not valid UTF-8".to_string())),
+    };
+    session.arena.reset();
+    Box::into_raw(Box::new(value))
+}
+
+/// Kind of a value.
+///
+/// # Safety
+///
+/// `value` must come from [`q_eval`] and not yet be freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn q_value_kind(value: *const QValue) -> QKind {
+    unsafe { (*value).kind }
+}
+
+/// Integer value of an int or boolean; 0 for other kinds.
+///
+/// # Safety
+///
+/// `value` must come from [`q_eval`] and not yet be freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn q_value_int(value: *const QValue) -> i64 {
+    unsafe { (*value).int }
+}
+
+/// Value of a number as a float (the real part of a complex number);
+/// NaN for errors.
+///
+/// # Safety
+///
+/// `value` must come from [`q_eval`] and not yet be freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn q_value_float(value: *const QValue) -> f64 {
+    unsafe { (*value).float }
+}
+
+/// Display form of a value (as the REPL prints it), or the error message.
+/// The string is owned by the value and lives until it is freed.
+///
+/// # Safety
+///
+/// `value` must come from [`q_eval`] and not yet be freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn q_value_string(value: *const QValue) -> *const c_char {
+    unsafe { (*value).text.as_ptr() }
+}
+
+/// Free a value. Null is ignored.
+///
+/// # Safety
+///
+/// `value` must be null or come from [`q_eval`], and must not be used
+/// afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn q_value_free(value: *mut QValue) {
+    if !value.is_null() {
+        drop(unsafe { Box::from_raw(value) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(session: *mut QSession, source: &str) -> (QKind, i64, f64, String) {
+        let source = CString::new(source).unwrap();
+        unsafe {
+            let value = q_eval(session, source.as_ptr());
+            let text = CStr::from_ptr(q_value_string(value))
+                .to_str()
+                .unwrap()
+                .to_string();
+            let result = (
+                q_value_kind(value),
+                q_value_int(value),
+                q_value_float(value),
+                text,
+            );
+            q_value_free(value);
+            result
+        }
+    }
+
+    #[test]
+    fn test_eval_through_c_api() {
+        let session = q_session_new();
+        assert_eq!(eval(session, "1+2"), (QKind::Int, 3, 3.0, "3".to_string()));
+        assert_eq!(
+            eval(session, "1<2"),
+            (QKind::Bool, 1, 1.0, "1b".to_string())
+        );
+        assert_eq!(eval(session, "7/2").1, 0);
+        assert_eq!(eval(session, "7/2").2, 3.5);
+        assert_eq!(
+            eval(session, "9223372036854775807+1"),
+            (
+                QKind::BigInt,
+                0,
+                9223372036854775808.0,
+                "9223372036854775808".to_string()
+            )
+        );
+
+        let (kind, _, float, text) = eval(session, "1/0");
+        assert_eq!(kind, QKind::Error);
+        assert!(float.is_nan());
+        assert!(text.starts_with("Division by zero"), "{text}");
+
+        unsafe { q_session_set_exact(session, true) };
+        assert_eq!(
+            eval(session, "1/4"),
+            (QKind::Rational, 0, 0.25, "1/4".to_string())
+        );
+        unsafe { q_session_free(session) };
+    }
+}
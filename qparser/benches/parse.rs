@@ -0,0 +1,22 @@
+use bumpalo::Bump;
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use qparser::parse;
+
+/// A long arithmetic expression, roughly the size of a large script line.
+fn large_input() -> String {
+    vec!["(1+2*3-4/5)<=7.5|2"; 500].join("+")
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let input = large_input();
+    let mut arena = Bump::new();
+    c.bench_function("parse_large", |b| {
+        b.iter(|| {
+            parse(&arena, black_box(&input)).unwrap();
+            arena.reset();
+        })
+    });
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,33 @@
+//! Differential testing over random programs.
+//!
+//! Usage: `cargo run -p qparser --example gen_tests -- [seed] [count]`
+//!
+//! Generates `count` programs from `seed` and reports every program whose
+//! source and pretty-printed AST disagree (see `qparser::generate::round_trip`).
+
+use qparser::generate::{Generator, round_trip};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let seed: u64 = args
+        .next()
+        .map_or(0, |s| s.parse().expect("seed must be a number"));
+    let count: usize = args
+        .next()
+        .map_or(10_000, |s| s.parse().expect("count must be a number"));
+    let mut generator = Generator::new(seed);
+    let mut divergences = 0;
+    for _ in 0..count {
+        if let Err(divergence) = round_trip(&generator.expr(4)) {
+            println!("{}", divergence);
+            divergences += 1;
+        }
+    }
+    println!(
+        "seed {}: {} programs, {} divergences",
+        seed, count, divergences
+    );
+    if divergences > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,250 @@
+//! Arithmetic over the numeric tower: int → rational → float → complex.
+//!
+//! Operands are promoted to the wider of their two types before the verb is
+//! applied. Rationals only arise in exact mode (`Config::exact`), where
+//! dividing two ints keeps the exact quotient instead of rounding to a float.
+//! Complex results with a zero imaginary part collapse back to floats.
+//! Int arithmetic that overflows `i64` is redone on big integers, and big
+//! results that fit back in an `i64` become ints again.
+
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::{BigRational, Ratio};
+use num_traits::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Signed, ToPrimitive};
+
+use crate::ast::Expr;
+
+/// An arithmetic verb.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl ArithOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            ArithOp::Add => "+",
+            ArithOp::Sub => "-",
+            ArithOp::Mul => "*",
+            ArithOp::Div => "/",
+        }
+    }
+}
+
+/// A monadic numeric verb.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonadOp {
+    /// Absolute value; the magnitude of a complex number
+    Abs,
+    /// Phase angle in radians (0 or π for reals)
+    Arg,
+    /// Complex conjugate; reals are their own conjugate
+    Conj,
+    /// Numerator of an exact number; an int is its own numerator
+    Numerator,
+    /// Denominator of an exact number; 1 for an int
+    Denominator,
+}
+
+/// Build a rational atom, collapsing whole numbers to `Int`.
+pub fn rational<'a>(r: Ratio<i64>) -> Expr<'a> {
+    if r.is_integer() {
+        Expr::Int(r.to_integer())
+    } else {
+        Expr::Rational(r)
+    }
+}
+
+/// Build an int atom, using `Int` when the value fits in an `i64`.
+pub fn int<'a>(i: BigInt) -> Expr<'a> {
+    match i.to_i64() {
+        Some(i) => Expr::Int(i),
+        None => Expr::BigInt(i),
+    }
+}
+
+/// Build an exact atom from a big fraction: an int, or a rational if the
+/// fraction fits in 64 bits. `symbol` names the verb in the overflow error.
+fn big_rational<'a>(r: BigRational, symbol: &str) -> Result<Expr<'a>, String> {
+    if r.is_integer() {
+        return Ok(int(r.to_integer()));
+    }
+    match (r.numer().to_i64(), r.denom().to_i64()) {
+        (Some(n), Some(d)) => Ok(Expr::Rational(Ratio::new_raw(n, d))),
+        _ => Err(format!(
+            "Overflow: rational `{}` exceeds 64-bit range",
+            symbol
+        )),
+    }
+}
+
+fn as_big_rational(e: &Expr<'_>) -> BigRational {
+    match e {
+        Expr::Int(i) => BigRational::from_integer(BigInt::from(*i)),
+        Expr::BigInt(i) => BigRational::from_integer(i.clone()),
+        Expr::Rational(r) => BigRational::new(BigInt::from(*r.numer()), BigInt::from(*r.denom())),
+        _ => unreachable!("non-rational operand"),
+    }
+}
+
+fn as_f64(e: &Expr<'_>) -> f64 {
+    match e {
+        Expr::Int(i) => *i as f64,
+        Expr::BigInt(i) => i.to_f64().unwrap_or(f64::NAN),
+        Expr::Rational(r) => r.to_f64().unwrap_or(f64::NAN),
+        Expr::Float(x) => *x,
+        _ => unreachable!("non-numeric operand"),
+    }
+}
+
+/// Build a complex atom, collapsing a zero imaginary part to `Float`.
+pub fn complex<'a>(c: Complex64) -> Expr<'a> {
+    if c.im == 0.0 {
+        Expr::Float(c.re)
+    } else {
+        Expr::Complex(c)
+    }
+}
+
+/// A numeric atom as a complex number.
+pub fn as_complex(e: &Expr<'_>) -> Complex64 {
+    match e {
+        Expr::Complex(c) => *c,
+        _ => Complex64::new(as_f64(e), 0.0),
+    }
+}
+
+fn as_ratio(e: &Expr<'_>) -> Ratio<i64> {
+    match e {
+        Expr::Int(i) => Ratio::from_integer(*i),
+        Expr::Rational(r) => *r,
+        _ => unreachable!("non-rational operand"),
+    }
+}
+
+/// Apply `op` to two numeric atoms. The caller has already rejected
+/// non-numeric operands and division by zero.
+pub fn apply<'a>(
+    op: ArithOp,
+    left: Expr<'a>,
+    right: Expr<'a>,
+    exact: bool,
+) -> Result<Expr<'a>, String> {
+    let is_int = |e: &Expr<'_>| matches!(e, Expr::Int(_) | Expr::BigInt(_));
+    match (&left, &right) {
+        (Expr::Int(a), Expr::Int(b)) if op != ArithOp::Div => {
+            let result = match op {
+                ArithOp::Add => a.checked_add(b),
+                ArithOp::Sub => a.checked_sub(b),
+                ArithOp::Mul => a.checked_mul(b),
+                ArithOp::Div => unreachable!(),
+            };
+            match result {
+                Some(i) => Ok(Expr::Int(i)),
+                None => big_int_op(op, &left, &right),
+            }
+        }
+        _ if is_int(&left) && is_int(&right) && op != ArithOp::Div => big_int_op(op, &left, &right),
+        (Expr::Complex(_), _) | (_, Expr::Complex(_)) => {
+            let (a, b) = (as_complex(&left), as_complex(&right));
+            Ok(complex(match op {
+                ArithOp::Add => a + b,
+                ArithOp::Sub => a - b,
+                ArithOp::Mul => a * b,
+                ArithOp::Div => a / b,
+            }))
+        }
+        (Expr::Float(_), _) | (_, Expr::Float(_)) => {
+            Ok(float_op(op, as_f64(&left), as_f64(&right)))
+        }
+        _ if is_int(&left) && is_int(&right) && !exact => {
+            Ok(float_op(op, as_f64(&left), as_f64(&right)))
+        }
+        (Expr::BigInt(_), _) | (_, Expr::BigInt(_)) => big_rational_op(op, &left, &right),
+        _ => {
+            let (a, b) = (as_ratio(&left), as_ratio(&right));
+            let result = match op {
+                ArithOp::Add => a.checked_add(&b),
+                ArithOp::Sub => a.checked_sub(&b),
+                ArithOp::Mul => a.checked_mul(&b),
+                ArithOp::Div => a.checked_div(&b),
+            };
+            match result {
+                Some(r) => Ok(rational(r)),
+                None => big_rational_op(op, &left, &right),
+            }
+        }
+    }
+}
+
+/// Apply `op` (not division) to two int atoms on big integers.
+fn big_int_op<'a>(op: ArithOp, left: &Expr<'_>, right: &Expr<'_>) -> Result<Expr<'a>, String> {
+    let as_big = |e: &Expr<'_>| match e {
+        Expr::Int(i) => BigInt::from(*i),
+        Expr::BigInt(i) => i.clone(),
+        _ => unreachable!("non-int operand"),
+    };
+    let (a, b) = (as_big(left), as_big(right));
+    Ok(int(match op {
+        ArithOp::Add => a + b,
+        ArithOp::Sub => a - b,
+        ArithOp::Mul => a * b,
+        ArithOp::Div => unreachable!("int division gives a float or rational"),
+    }))
+}
+
+/// Apply `op` to two exact atoms on big fractions.
+fn big_rational_op<'a>(op: ArithOp, left: &Expr<'_>, right: &Expr<'_>) -> Result<Expr<'a>, String> {
+    let (a, b) = (as_big_rational(left), as_big_rational(right));
+    big_rational(
+        match op {
+            ArithOp::Add => a + b,
+            ArithOp::Sub => a - b,
+            ArithOp::Mul => a * b,
+            ArithOp::Div => a / b,
+        },
+        op.symbol(),
+    )
+}
+
+fn float_op<'a>(op: ArithOp, a: f64, b: f64) -> Expr<'a> {
+    Expr::Float(match op {
+        ArithOp::Add => a + b,
+        ArithOp::Sub => a - b,
+        ArithOp::Mul => a * b,
+        ArithOp::Div => a / b,
+    })
+}
+
+/// Apply a monadic verb to a numeric atom. The caller has already rejected
+/// non-numeric operands.
+pub fn apply_monad<'a>(op: MonadOp, value: Expr<'a>) -> Result<Expr<'a>, String> {
+    Ok(match (op, value) {
+        (MonadOp::Abs, Expr::Int(i)) => match i.checked_abs() {
+            Some(i) => Expr::Int(i),
+            None => Expr::BigInt(BigInt::from(i).abs()),
+        },
+        (MonadOp::Abs, Expr::BigInt(i)) => Expr::BigInt(i.abs()),
+        // The numerator may be i64::MIN, whose negation does not fit
+        (MonadOp::Abs, Expr::Rational(r)) if r.numer().is_negative() => {
+            match r.numer().checked_neg() {
+                Some(n) => Expr::Rational(Ratio::new_raw(n, *r.denom())),
+                None => big_rational(as_big_rational(&Expr::Rational(r)).abs(), "abs")?,
+            }
+        }
+        (MonadOp::Abs, Expr::Rational(r)) => Expr::Rational(r),
+        (MonadOp::Abs, Expr::Float(x)) => Expr::Float(x.abs()),
+        (MonadOp::Abs, Expr::Complex(c)) => Expr::Float(c.norm()),
+        (MonadOp::Arg, value) => Expr::Float(as_complex(&value).arg()),
+        (MonadOp::Conj, Expr::Complex(c)) => Expr::Complex(c.conj()),
+        (MonadOp::Conj, value) => value,
+        (MonadOp::Numerator, Expr::Rational(r)) => Expr::Int(*r.numer()),
+        (MonadOp::Numerator, value) => value,
+        (MonadOp::Denominator, Expr::Rational(r)) => Expr::Int(*r.denom()),
+        (MonadOp::Denominator, _) => Expr::Int(1),
+        _ => unreachable!("non-numeric operand"),
+    })
+}
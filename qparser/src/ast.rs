@@ -1,88 +1,638 @@
+use std::cmp::Ordering;
+use std::ops::Deref;
+
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::{BigRational, Ratio};
+use num_traits::{FromPrimitive, Zero};
+
+use crate::arith::{self, ArithOp, MonadOp};
+use crate::error::EvalError;
+use crate::ops;
+use crate::{Config, EvalHook, EvalStats};
+
+/// Character range of a node in the source text.
+pub type Span = std::ops::Range<usize>;
+
+/// An expression together with the span of source it was parsed from.
+/// Dereferences to the expression itself.
+#[derive(Debug, PartialEq)]
+pub struct Spanned<'a> {
+    pub expr: Expr<'a>,
+    pub span: Span,
+}
+
+impl<'a> Deref for Spanned<'a> {
+    type Target = Expr<'a>;
+
+    fn deref(&self) -> &Expr<'a> {
+        &self.expr
+    }
+}
+
+impl std::fmt::Display for Spanned<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.expr.fmt(f)
+    }
+}
+
 /// AST for Q expressions: Phase 0 atoms & basic arithmetic.
+///
+/// Child nodes are borrowed from a `bumpalo::Bump` arena owned by the caller,
+/// so a whole tree is freed at once when the arena is reset or dropped.
+/// Each child carries its source span for error reporting.
 #[derive(Debug, PartialEq)]
-pub enum Expr {
+pub enum Expr<'a> {
     /// Integer literal
     Int(i64),
+    /// Integer outside `i64` range; only produced by arithmetic that
+    /// overflows, never by the parser
+    BigInt(BigInt),
     /// Floating-point literal
     Float(f64),
+    /// Exact fraction in lowest terms with denominator > 1 (exact mode only)
+    Rational(Ratio<i64>),
+    /// Complex number; literals are imaginary (`4i`)
+    Complex(Complex64),
+    /// Boolean atom (result of a comparison)
+    Bool(bool),
+    /// Absolute value or complex magnitude (`abs`)
+    Abs(&'a Spanned<'a>),
+    /// Phase angle (`arg`)
+    Arg(&'a Spanned<'a>),
+    /// Complex conjugate (`conj`)
+    Conj(&'a Spanned<'a>),
+    /// Numerator of an int or rational (`numerator`)
+    Numerator(&'a Spanned<'a>),
+    /// Denominator of an int or rational (`denominator`)
+    Denominator(&'a Spanned<'a>),
     /// Addition
-    Add(Box<Expr>, Box<Expr>),
+    Add(&'a Spanned<'a>, &'a Spanned<'a>),
     /// Subtraction
-    Sub(Box<Expr>, Box<Expr>),
+    Sub(&'a Spanned<'a>, &'a Spanned<'a>),
     /// Multiplication
-    Mul(Box<Expr>, Box<Expr>),
+    Mul(&'a Spanned<'a>, &'a Spanned<'a>),
     /// Division
-    Div(Box<Expr>, Box<Expr>),
+    Div(&'a Spanned<'a>, &'a Spanned<'a>),
+    /// Less than
+    Lt(&'a Spanned<'a>, &'a Spanned<'a>),
+    /// Greater than
+    Gt(&'a Spanned<'a>, &'a Spanned<'a>),
+    /// Less than or equal
+    Le(&'a Spanned<'a>, &'a Spanned<'a>),
+    /// Greater than or equal
+    Ge(&'a Spanned<'a>, &'a Spanned<'a>),
+    /// Equality
+    Eq(&'a Spanned<'a>, &'a Spanned<'a>),
+    /// Inequality
+    Ne(&'a Spanned<'a>, &'a Spanned<'a>),
+    /// Greater of two atoms (`|`)
+    Max(&'a Spanned<'a>, &'a Spanned<'a>),
+    /// Lesser of two atoms (`&`)
+    Min(&'a Spanned<'a>, &'a Spanned<'a>),
 }
 // Pretty-print atomic AST nodes
-impl std::fmt::Display for Expr {
+impl std::fmt::Display for Expr<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Expr::Int(i) => write!(f, "{}", i),
+            Expr::BigInt(i) => write!(f, "{}", i),
             Expr::Float(x) => write!(f, "{}", x),
+            Expr::Rational(r) => write!(f, "{}", r),
+            // Pure imaginaries print as literals (`4i`), so printing round-trips
+            Expr::Complex(c) if c.re == 0.0 => write!(f, "{}i", c.im),
+            Expr::Complex(c) => write!(f, "{}", c),
+            Expr::Bool(b) => write!(f, "{}b", *b as u8),
+            Expr::Abs(x) => write!(f, "abs({})", x),
+            Expr::Arg(x) => write!(f, "arg({})", x),
+            Expr::Conj(x) => write!(f, "conj({})", x),
+            Expr::Numerator(x) => write!(f, "numerator({})", x),
+            Expr::Denominator(x) => write!(f, "denominator({})", x),
             Expr::Add(l, r) => write!(f, "({} + {})", l, r),
             Expr::Sub(l, r) => write!(f, "({} - {})", l, r),
             Expr::Mul(l, r) => write!(f, "({} * {})", l, r),
             Expr::Div(l, r) => write!(f, "({} / {})", l, r),
+            Expr::Lt(l, r) => write!(f, "({} < {})", l, r),
+            Expr::Gt(l, r) => write!(f, "({} > {})", l, r),
+            Expr::Le(l, r) => write!(f, "({} <= {})", l, r),
+            Expr::Ge(l, r) => write!(f, "({} >= {})", l, r),
+            Expr::Eq(l, r) => write!(f, "({} = {})", l, r),
+            Expr::Ne(l, r) => write!(f, "({} <> {})", l, r),
+            Expr::Max(l, r) => write!(f, "({} | {})", l, r),
+            Expr::Min(l, r) => write!(f, "({} & {})", l, r),
         }
     }
 }
 
-impl Expr {
-    /// Evaluate the AST into a new atomic Expr.
-    pub fn eval(&self) -> Result<Expr, String> {
+/// Canonical form of an atom, used for equality, ordering and hashing.
+///
+/// Numeric atoms normalize so that values which compare equal have equal
+/// keys: a float with an integral value in `i64` range becomes `Int`
+/// (so `1 = 1.0` and `0.0 = -0.0`), and outside it `BigInt`, a float that is exactly a fraction
+/// with a 64-bit denominator becomes `Rational` (so `1/2 = 0.5` in exact
+/// mode), and every NaN becomes the single float null `Null`, which equals
+/// itself and sorts before all other numbers (as `0n` does in q).
+/// Booleans sort before every number.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AtomKey {
+    Bool(bool),
+    Null,
+    Int(i64),
+    /// Integer outside `i64` range
+    BigInt(BigInt),
+    /// Non-integral fraction in lowest terms
+    Rational(Ratio<i64>),
+    /// Bit pattern of a non-NaN float that is neither an integer nor a
+    /// fraction with an `i64` denominator.
+    Float(u64),
+}
+
+/// 2^63 as an f64; the exclusive upper bound of `i64`.
+const I64_BOUND: f64 = 9_223_372_036_854_775_808.0;
+
+impl AtomKey {
+    fn from_f64(x: f64) -> AtomKey {
+        if x.is_nan() {
+            AtomKey::Null
+        } else if x.fract() == 0.0 && (-I64_BOUND..I64_BOUND).contains(&x) {
+            AtomKey::Int(x as i64)
+        } else if x.is_finite() && x.fract() == 0.0 {
+            AtomKey::BigInt(BigInt::from_f64(x).unwrap())
+        } else if let Some(r) = dyadic(x) {
+            AtomKey::Rational(r)
+        } else {
+            AtomKey::Float(x.to_bits())
+        }
+    }
+
+    fn rank(&self) -> u8 {
         match self {
-            Expr::Int(i) => Ok(Expr::Int(*i)),
-            Expr::Float(f) => Ok(Expr::Float(*f)),
-            Expr::Add(l, r) => {
-                let left = l.eval()?;
-                let right = r.eval()?;
-                match (left, right) {
-                    (Expr::Int(a), Expr::Int(b)) => Ok(Expr::Int(a + b)),
-                    (Expr::Int(a), Expr::Float(b)) => Ok(Expr::Float(a as f64 + b)),
-                    (Expr::Float(a), Expr::Int(b)) => Ok(Expr::Float(a + b as f64)),
-                    (Expr::Float(a), Expr::Float(b)) => Ok(Expr::Float(a + b)),
-                    _ => Err("Type error in addition".into()),
-                }
+            AtomKey::Bool(_) => 0,
+            AtomKey::Null => 1,
+            AtomKey::Int(_) | AtomKey::BigInt(_) | AtomKey::Rational(_) | AtomKey::Float(_) => 2,
+        }
+    }
+
+    /// A finite number as an exact fraction, or the direction of an
+    /// infinity relative to every finite number.
+    fn exact(&self) -> Result<BigRational, Ordering> {
+        match self {
+            AtomKey::Int(i) => Ok(BigRational::from_integer(BigInt::from(*i))),
+            AtomKey::BigInt(i) => Ok(BigRational::from_integer(i.clone())),
+            AtomKey::Rational(r) => Ok(BigRational::new(
+                BigInt::from(*r.numer()),
+                BigInt::from(*r.denom()),
+            )),
+            AtomKey::Float(bits) => {
+                let f = f64::from_bits(*bits);
+                BigRational::from_float(f).ok_or(if f > 0.0 {
+                    Ordering::Greater
+                } else {
+                    Ordering::Less
+                })
             }
-            Expr::Sub(l, r) => {
-                let left = l.eval()?;
-                let right = r.eval()?;
-                match (left, right) {
-                    (Expr::Int(a), Expr::Int(b)) => Ok(Expr::Int(a - b)),
-                    (Expr::Int(a), Expr::Float(b)) => Ok(Expr::Float(a as f64 - b)),
-                    (Expr::Float(a), Expr::Int(b)) => Ok(Expr::Float(a - b as f64)),
-                    (Expr::Float(a), Expr::Float(b)) => Ok(Expr::Float(a - b)),
-                    _ => Err("Type error in subtraction".into()),
-                }
+            AtomKey::Bool(_) | AtomKey::Null => unreachable!("not a number"),
+        }
+    }
+}
+
+/// A finite, non-integral float as an exact fraction `m / 2^k`, if the
+/// denominator fits in an `i64` (k <= 62).
+fn dyadic(x: f64) -> Option<Ratio<i64>> {
+    if !x.is_finite() {
+        return None;
+    }
+    let bits = x.to_bits();
+    let biased = ((bits >> 52) & 0x7ff) as i64;
+    let fraction = bits & ((1 << 52) - 1);
+    // Subnormals have no implicit leading bit and a fixed exponent
+    let (mut mantissa, mut exp) = if biased == 0 {
+        (fraction, -1074)
+    } else {
+        (fraction | (1 << 52), biased - 1075)
+    };
+    let shift = mantissa.trailing_zeros() as i64;
+    mantissa >>= shift;
+    exp += shift;
+    if !(-62..0).contains(&exp) {
+        return None;
+    }
+    let numer = if x < 0.0 {
+        -(mantissa as i64)
+    } else {
+        mantissa as i64
+    };
+    Some(Ratio::new_raw(numer, 1 << -exp))
+}
+
+/// Exact comparison of a fraction with a float that has no `i64` fraction
+/// form (see [`dyadic`]), so is never equal to it.
+fn cmp_ratio_float(r: &Ratio<i64>, f: f64) -> Ordering {
+    match BigRational::from_float(f) {
+        Some(f) => BigRational::new(BigInt::from(*r.numer()), BigInt::from(*r.denom())).cmp(&f),
+        // Infinity
+        None if f > 0.0 => Ordering::Less,
+        None => Ordering::Greater,
+    }
+}
+
+/// Exact comparison of an integer with a float that is either non-integral
+/// or outside `i64` range, so never equal to it.
+fn cmp_int_float(i: i64, f: f64) -> Ordering {
+    if f >= I64_BOUND {
+        Ordering::Less
+    } else if f < -I64_BOUND {
+        Ordering::Greater
+    } else if i <= f.floor() as i64 {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
+}
+
+impl Ord for AtomKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (AtomKey::Bool(a), AtomKey::Bool(b)) => a.cmp(b),
+            (AtomKey::Int(a), AtomKey::Int(b)) => a.cmp(b),
+            (AtomKey::Int(a), AtomKey::Float(b)) => cmp_int_float(*a, f64::from_bits(*b)),
+            (AtomKey::Float(a), AtomKey::Int(b)) => cmp_int_float(*b, f64::from_bits(*a)).reverse(),
+            (AtomKey::Int(a), AtomKey::Rational(b)) => Ratio::from_integer(*a).cmp(b),
+            (AtomKey::Rational(a), AtomKey::Int(b)) => a.cmp(&Ratio::from_integer(*b)),
+            (AtomKey::Rational(a), AtomKey::Rational(b)) => a.cmp(b),
+            (AtomKey::Rational(a), AtomKey::Float(b)) => cmp_ratio_float(a, f64::from_bits(*b)),
+            (AtomKey::Float(a), AtomKey::Rational(b)) => {
+                cmp_ratio_float(b, f64::from_bits(*a)).reverse()
             }
-            Expr::Mul(l, r) => {
-                let left = l.eval()?;
-                let right = r.eval()?;
-                match (left, right) {
-                    (Expr::Int(a), Expr::Int(b)) => Ok(Expr::Int(a * b)),
-                    (Expr::Int(a), Expr::Float(b)) => Ok(Expr::Float(a as f64 * b)),
-                    (Expr::Float(a), Expr::Int(b)) => Ok(Expr::Float(a * b as f64)),
-                    (Expr::Float(a), Expr::Float(b)) => Ok(Expr::Float(a * b)),
-                    _ => Err("Type error in multiplication".into()),
+            (AtomKey::Float(a), AtomKey::Float(b)) => {
+                f64::from_bits(*a).total_cmp(&f64::from_bits(*b))
+            }
+            (AtomKey::BigInt(_), _) | (_, AtomKey::BigInt(_))
+                if self.rank() == 2 && other.rank() == 2 =>
+            {
+                match (self.exact(), other.exact()) {
+                    (Ok(a), Ok(b)) => a.cmp(&b),
+                    (Err(a), Err(b)) => a.cmp(&b),
+                    (Err(a), Ok(_)) => a,
+                    (Ok(_), Err(b)) => b.reverse(),
                 }
             }
-            Expr::Div(l, r) => {
-                let left = l.eval()?;
-                let right = r.eval()?;
-                match &right {
-                    Expr::Int(b) if *b == 0 => return Err("Division by zero".into()),
-                    Expr::Float(b) if *b == 0.0 => return Err("Division by zero".into()),
-                    _ => (),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for AtomKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Expr<'a> {
+    /// Operator symbol of a verb node (see [`ops::OPERATORS`]), or `None` for atoms.
+    pub fn symbol(&self) -> Option<&'static str> {
+        match self {
+            Expr::Int(_)
+            | Expr::BigInt(_)
+            | Expr::Float(_)
+            | Expr::Rational(_)
+            | Expr::Complex(_)
+            | Expr::Bool(_) => None,
+            Expr::Abs(_) => Some("abs"),
+            Expr::Arg(_) => Some("arg"),
+            Expr::Conj(_) => Some("conj"),
+            Expr::Numerator(_) => Some("numerator"),
+            Expr::Denominator(_) => Some("denominator"),
+            Expr::Add(..) => Some("+"),
+            Expr::Sub(..) => Some("-"),
+            Expr::Mul(..) => Some("*"),
+            Expr::Div(..) => Some("/"),
+            Expr::Lt(..) => Some("<"),
+            Expr::Gt(..) => Some(">"),
+            Expr::Le(..) => Some("<="),
+            Expr::Ge(..) => Some(">="),
+            Expr::Eq(..) => Some("="),
+            Expr::Ne(..) => Some("<>"),
+            Expr::Max(..) => Some("|"),
+            Expr::Min(..) => Some("&"),
+        }
+    }
+
+    /// Canonical key of an atom, or `None` for unevaluated expressions and
+    /// complex numbers, which are unordered.
+    pub fn key(&self) -> Option<AtomKey> {
+        match self {
+            Expr::Bool(b) => Some(AtomKey::Bool(*b)),
+            Expr::Int(i) => Some(AtomKey::Int(*i)),
+            Expr::BigInt(i) => Some(AtomKey::BigInt(i.clone())),
+            Expr::Float(x) => Some(AtomKey::from_f64(*x)),
+            Expr::Rational(r) => Some(AtomKey::Rational(*r)),
+            _ => None,
+        }
+    }
+
+    /// Compare two atoms by their canonical keys (see [`AtomKey`]).
+    ///
+    /// Atoms are totally ordered across types: booleans sort before all
+    /// numbers, and ints and floats compare exactly by numeric value. Returns
+    /// `None` if either side is not an atom.
+    pub fn compare(&self, other: &Expr<'_>) -> Option<Ordering> {
+        Some(self.key()?.cmp(&other.key()?))
+    }
+
+    /// Article and type name of an atom for diagnostics, e.g. "a boolean".
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Expr::Int(_) | Expr::BigInt(_) => "an int",
+            Expr::Float(_) => "a float",
+            Expr::Rational(_) => "a rational",
+            Expr::Complex(_) => "a complex number",
+            Expr::Bool(_) => "a boolean",
+            _ => "an unevaluated expression",
+        }
+    }
+
+    /// Type error pointing at `operand` unless its `value` is a number
+    /// this verb accepts.
+    fn check_numeric(
+        &self,
+        which: &str,
+        operand: &Spanned<'a>,
+        value: &Expr<'a>,
+    ) -> Result<(), EvalError> {
+        if self.accepts(value) {
+            return Ok(());
+        }
+        let message = format!(
+            "{}, but {} is {}",
+            ops::type_error(self.symbol().unwrap_or_default()),
+            which,
+            value.describe()
+        );
+        Err(EvalError::at(message, operand.span.clone()))
+    }
+
+    /// Whether this verb takes `value` as an operand: only exact numbers
+    /// have a numerator and denominator, other verbs take any number.
+    fn accepts(&self, value: &Expr<'_>) -> bool {
+        match self {
+            Expr::Numerator(_) | Expr::Denominator(_) => {
+                matches!(value, Expr::Int(_) | Expr::BigInt(_) | Expr::Rational(_))
+            }
+            _ => value.is_numeric(),
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Expr::Int(_) | Expr::BigInt(_) | Expr::Float(_) | Expr::Rational(_) | Expr::Complex(_)
+        )
+    }
+
+    /// Evaluate an arithmetic verb (see [`arith::apply`]) on its evaluated
+    /// operands. A non-numeric operand is a type error pointing at that
+    /// operand's span.
+    fn eval_arith(
+        &self,
+        op: ArithOp,
+        (left, right): (Expr<'a>, Expr<'a>),
+        l: &Spanned<'a>,
+        r: &Spanned<'a>,
+        config: &Config,
+    ) -> Result<Expr<'a>, EvalError> {
+        for (side, operand, value) in [("left", l, &left), ("right", r, &right)] {
+            self.check_numeric(&format!("its {} operand", side), operand, value)?;
+        }
+        // Zero of any real type (including -0.0) has the key Int(0)
+        let zero = match &right {
+            Expr::Complex(c) => c.is_zero(),
+            _ => right.key() == Some(AtomKey::Int(0)),
+        };
+        if op == ArithOp::Div && zero {
+            return Err(EvalError::at("Division by zero", r.span.clone()));
+        }
+        Ok(arith::apply(op, left, right, config.exact)?)
+    }
+
+    /// Evaluate a monadic numeric verb (see [`arith::apply_monad`]).
+    fn eval_monad(
+        &self,
+        op: MonadOp,
+        x: &Spanned<'a>,
+        config: &Config,
+        level: Level,
+        hook: &dyn EvalHook,
+    ) -> Result<Expr<'a>, EvalError> {
+        let value = x.eval_at(config, level.operand(), hook)?;
+        self.check_numeric("its operand", x, &value)?;
+        Ok(arith::apply_monad(op, value)?)
+    }
+
+    /// Compare evaluated operands, mapping the ordering to a boolean atom.
+    fn eval_cmp(
+        &self,
+        (left, right): (Expr<'a>, Expr<'a>),
+        l: &Spanned<'a>,
+        r: &Spanned<'a>,
+        test: fn(Ordering) -> bool,
+    ) -> Result<Expr<'a>, EvalError> {
+        // Complex numbers only support (in)equality
+        if matches!(left, Expr::Complex(_)) || matches!(right, Expr::Complex(_)) {
+            let equal = left.is_numeric()
+                && right.is_numeric()
+                && arith::as_complex(&left) == arith::as_complex(&right);
+            return match self {
+                Expr::Eq(..) => Ok(Expr::Bool(equal)),
+                Expr::Ne(..) => Ok(Expr::Bool(!equal)),
+                _ => Err(self.unordered((l, &left), (r, &right))),
+            };
+        }
+        match left.compare(&right) {
+            Some(ord) => Ok(Expr::Bool(test(ord))),
+            None => Err(self.unordered((l, &left), (r, &right))),
+        }
+    }
+
+    /// Type error pointing at the first operand that has no place in the
+    /// order of atoms.
+    fn unordered(
+        &self,
+        (l, left): (&Spanned<'a>, &Expr<'a>),
+        (r, right): (&Spanned<'a>, &Expr<'a>),
+    ) -> EvalError {
+        let (side, operand, value) = match left.key() {
+            None => ("left", l, left),
+            Some(_) => ("right", r, right),
+        };
+        let symbol = self.symbol().unwrap_or_default();
+        let message = match value {
+            Expr::Complex(_) => format!("Type error: `{}` cannot order complex numbers", symbol),
+            _ => format!(
+                "{}, but its {} operand is {}",
+                ops::type_error(symbol),
+                side,
+                value.describe()
+            ),
+        };
+        EvalError::at(message, operand.span.clone())
+    }
+
+    /// The operands of a dyadic verb.
+    fn operands(&self) -> Option<(&'a Spanned<'a>, &'a Spanned<'a>)> {
+        match *self {
+            Expr::Add(l, r)
+            | Expr::Sub(l, r)
+            | Expr::Mul(l, r)
+            | Expr::Div(l, r)
+            | Expr::Lt(l, r)
+            | Expr::Gt(l, r)
+            | Expr::Le(l, r)
+            | Expr::Ge(l, r)
+            | Expr::Eq(l, r)
+            | Expr::Ne(l, r)
+            | Expr::Max(l, r)
+            | Expr::Min(l, r) => Some((l, r)),
+            _ => None,
+        }
+    }
+
+    /// Evaluate this dyadic verb's right operand `r` and apply the verb to
+    /// `left`, the value of its left operand `l`.
+    fn eval_dyad(
+        &self,
+        left: Expr<'a>,
+        l: &Spanned<'a>,
+        r: &Spanned<'a>,
+        config: &Config,
+        level: Level,
+        hook: &dyn EvalHook,
+    ) -> Result<Expr<'a>, EvalError> {
+        let operands = (left, r.eval_at(config, level.operand(), hook)?);
+        match self {
+            Expr::Add(..) => self.eval_arith(ArithOp::Add, operands, l, r, config),
+            Expr::Sub(..) => self.eval_arith(ArithOp::Sub, operands, l, r, config),
+            Expr::Mul(..) => self.eval_arith(ArithOp::Mul, operands, l, r, config),
+            Expr::Div(..) => self.eval_arith(ArithOp::Div, operands, l, r, config),
+            Expr::Lt(..) => self.eval_cmp(operands, l, r, |o| o == Ordering::Less),
+            Expr::Gt(..) => self.eval_cmp(operands, l, r, |o| o == Ordering::Greater),
+            Expr::Le(..) => self.eval_cmp(operands, l, r, |o| o != Ordering::Greater),
+            Expr::Ge(..) => self.eval_cmp(operands, l, r, |o| o != Ordering::Less),
+            Expr::Eq(..) => self.eval_cmp(operands, l, r, |o| o == Ordering::Equal),
+            Expr::Ne(..) => self.eval_cmp(operands, l, r, |o| o != Ordering::Equal),
+            _ => {
+                let (left, right) = operands;
+                let ord = left
+                    .compare(&right)
+                    .ok_or_else(|| self.unordered((l, &left), (r, &right)))?;
+                let take_left = match self {
+                    Expr::Max(..) => ord != Ordering::Less,
+                    _ => ord != Ordering::Greater,
                 };
-                match (left, right) {
-                    (Expr::Int(a), Expr::Int(b)) => Ok(Expr::Float(a as f64 / b as f64)),
-                    (Expr::Int(a), Expr::Float(b)) => Ok(Expr::Float(a as f64 / b)),
-                    (Expr::Float(a), Expr::Int(b)) => Ok(Expr::Float(a / b as f64)),
-                    (Expr::Float(a), Expr::Float(b)) => Ok(Expr::Float(a / b)),
-                    _ => Err("Type error in division".into()),
-                }
+                Ok(if take_left { left } else { right })
             }
         }
     }
+
+    /// Evaluate the AST into a new atomic Expr.
+    pub fn eval(&self) -> Result<Expr<'a>, EvalError> {
+        self.eval_with(&Config::default())
+    }
+
+    /// Evaluate the AST under the limits in `config`.
+    pub fn eval_with(&self, config: &Config) -> Result<Expr<'a>, EvalError> {
+        self.eval_counted(config, &EvalStats::default())
+    }
+
+    /// Like [`Expr::eval_with`], also counting the work done in `stats`.
+    pub fn eval_counted(&self, config: &Config, stats: &EvalStats) -> Result<Expr<'a>, EvalError> {
+        self.eval_hooked(config, stats)
+    }
+
+    /// Like [`Expr::eval_with`], calling `hook` on entry to and exit from
+    /// every node evaluated.
+    pub fn eval_hooked(&self, config: &Config, hook: &dyn EvalHook) -> Result<Expr<'a>, EvalError> {
+        self.eval_at(config, Level::default(), hook)
+    }
+
+    fn eval_at(
+        &self,
+        config: &Config,
+        level: Level,
+        hook: &dyn EvalHook,
+    ) -> Result<Expr<'a>, EvalError> {
+        // Walk down the left operands of a chain like `1+2+3` in a loop,
+        // so flat chains of any length take no stack per operator
+        let mut spine = Vec::new();
+        let (mut node, mut at) = (self, level);
+        while let Some((l, r)) = node.operands() {
+            hook.enter(node, at.depth);
+            spine.push((node, l, r, at));
+            (node, at) = (&l.expr, at.left());
+        }
+        hook.enter(node, at.depth);
+        let mut result = node.eval_node(config, at, hook);
+        hook.exit(node, at.depth, &result);
+        for (node, l, r, at) in spine.into_iter().rev() {
+            result = result.and_then(|left| node.eval_dyad(left, l, r, config, at, hook));
+            hook.exit(node, at.depth, &result);
+        }
+        result
+    }
+
+    /// Evaluate an atom or monadic verb, its operand through
+    /// [`Expr::eval_at`].
+    fn eval_node(
+        &self,
+        config: &Config,
+        level: Level,
+        hook: &dyn EvalHook,
+    ) -> Result<Expr<'a>, EvalError> {
+        if level.nesting > config.max_eval_depth {
+            return Err(format!(
+                "Too deep: expression nests more than {} levels",
+                config.max_eval_depth
+            )
+            .into());
+        }
+        match self {
+            Expr::Int(i) => Ok(Expr::Int(*i)),
+            Expr::BigInt(i) => Ok(Expr::BigInt(i.clone())),
+            Expr::Float(f) => Ok(Expr::Float(*f)),
+            Expr::Rational(r) => Ok(Expr::Rational(*r)),
+            Expr::Complex(c) => Ok(Expr::Complex(*c)),
+            Expr::Bool(b) => Ok(Expr::Bool(*b)),
+            Expr::Abs(x) => self.eval_monad(MonadOp::Abs, x, config, level, hook),
+            Expr::Arg(x) => self.eval_monad(MonadOp::Arg, x, config, level, hook),
+            Expr::Conj(x) => self.eval_monad(MonadOp::Conj, x, config, level, hook),
+            Expr::Numerator(x) => self.eval_monad(MonadOp::Numerator, x, config, level, hook),
+            Expr::Denominator(x) => self.eval_monad(MonadOp::Denominator, x, config, level, hook),
+            _ => unreachable!("dyadic verbs are evaluated by `eval_at`"),
+        }
+    }
+}
+
+/// How far below the root a node is evaluated: `depth` levels in all,
+/// `nesting` of them through right or monadic operands. Only `nesting` is
+/// limited, since left operands are walked in a loop.
+#[derive(Debug, Clone, Copy, Default)]
+struct Level {
+    depth: usize,
+    nesting: usize,
+}
+
+impl Level {
+    fn left(self) -> Level {
+        Level {
+            depth: self.depth + 1,
+            ..self
+        }
+    }
+
+    fn operand(self) -> Level {
+        Level {
+            depth: self.depth + 1,
+            nesting: self.nesting + 1,
+        }
+    }
 }
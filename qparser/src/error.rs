@@ -0,0 +1,61 @@
+//! Evaluation errors.
+
+use std::fmt;
+
+use crate::ast::Span;
+
+/// An evaluation failure, optionally pointing at the operand that caused it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalError {
+    pub message: String,
+    /// Source span of the offending operand, if known
+    pub span: Option<Span>,
+}
+
+impl EvalError {
+    /// An error pointing at `span`.
+    pub fn at(message: impl Into<String>, span: Span) -> Self {
+        EvalError {
+            message: message.into(),
+            span: Some(span),
+        }
+    }
+
+    /// Render the message, followed by `source` with the offending span
+    /// underlined when there is one.
+    pub fn render(&self, source: &str) -> String {
+        match &self.span {
+            Some(span) => format!(
+                "{}\n  {}\n  {}{}",
+                self.message,
+                source,
+                " ".repeat(span.start),
+                "^".repeat(span.len().max(1))
+            ),
+            None => self.message.clone(),
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+impl From<String> for EvalError {
+    fn from(message: String) -> Self {
+        EvalError {
+            message,
+            span: None,
+        }
+    }
+}
+
+impl From<&str> for EvalError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
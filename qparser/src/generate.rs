@@ -0,0 +1,142 @@
+//! Seeded generator of random well-typed Q expressions, for differential
+//! testing of the parser, printer and evaluator.
+//!
+//! [`round_trip`] is the differential check: a program must evaluate the
+//! same whether it is run from its source or from its pretty-printed AST,
+//! and printing must be a fixpoint. Run many seeds with
+//! `cargo run -p qparser --example gen_tests -- <seed> <count>`.
+
+use bumpalo::Bump;
+
+use crate::{eval_str, parse};
+
+/// Deterministic expression generator; a seed always yields the same
+/// sequence of programs, so failures reproduce.
+pub struct Generator {
+    state: u64,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Generator {
+        Generator { state: seed }
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.state = self
+            .state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.state >> 33) as usize % n
+    }
+
+    fn pick<'s>(&mut self, items: &[&'s str]) -> &'s str {
+        items[self.below(items.len())]
+    }
+
+    /// Optional padding around a verb.
+    fn space(&mut self) -> &'static str {
+        self.pick(&["", " "])
+    }
+
+    /// An expression of any type: a number, a comparison yielding a
+    /// boolean, or the max/min of two such expressions, nesting at most
+    /// `depth` verbs. Well-typed, but it may still divide by zero or try
+    /// to order complex numbers.
+    pub fn expr(&mut self, depth: usize) -> String {
+        if depth == 0 {
+            return self.numeric(0);
+        }
+        match self.below(4) {
+            0 => {
+                let op = self.pick(&["<", ">", "<=", ">=", "=", "<>"]);
+                self.dyadic(op, depth, Generator::numeric)
+            }
+            1 => {
+                let op = self.pick(&["|", "&"]);
+                self.dyadic(op, depth, Generator::expr)
+            }
+            _ => self.numeric(depth),
+        }
+    }
+
+    /// A numeric expression nesting at most `depth` verbs. Int literals
+    /// stay below 10, so ints cannot overflow for `depth` up to 4 (at most
+    /// 16 literals, however precedence groups them).
+    pub fn numeric(&mut self, depth: usize) -> String {
+        if depth == 0 || self.below(3) == 0 {
+            return self.literal();
+        }
+        match self.below(6) {
+            0 => {
+                let verb = self.pick(&["abs", "arg", "conj"]);
+                let space = self.space();
+                let operand = self.numeric(depth - 1);
+                format!("{}{}({})", verb, space, operand)
+            }
+            1 => format!("({})", self.numeric(depth - 1)),
+            _ => {
+                let op = self.pick(&["+", "-", "*", "/"]);
+                self.dyadic(op, depth, Generator::numeric)
+            }
+        }
+    }
+
+    fn dyadic(
+        &mut self,
+        op: &str,
+        depth: usize,
+        operand: fn(&mut Generator, usize) -> String,
+    ) -> String {
+        let left = operand(self, depth - 1);
+        let (before, after) = (self.space(), self.space());
+        let right = operand(self, depth - 1);
+        // Unparenthesized: precedence may regroup the operands, which
+        // exercises the parser without affecting the round trip
+        format!("{}{}{}{}{}", left, before, op, after, right)
+    }
+
+    fn literal(&mut self) -> String {
+        let whole = self.below(10);
+        match self.below(5) {
+            0 => format!("{}.{}", whole, self.below(100)),
+            1 => format!("{}i", whole),
+            _ => whole.to_string(),
+        }
+    }
+}
+
+/// Check that `source` evaluates the same as its pretty-printed AST, and
+/// that printing that AST again changes nothing. Errors are compared by
+/// their first line, since the source excerpt under it differs.
+pub fn round_trip(source: &str) -> Result<(), String> {
+    let arena = Bump::new();
+    let printed = parse(&arena, source)
+        .map_err(|e| format!("{}: does not parse: {}", source, e))?
+        .to_string();
+    let reprinted = parse(&arena, &printed)
+        .map_err(|e| {
+            format!(
+                "{}: printed as {}, which does not parse: {}",
+                source, printed, e
+            )
+        })?
+        .to_string();
+    if printed != reprinted {
+        return Err(format!(
+            "{}: printed as {}, then as {}",
+            source, printed, reprinted
+        ));
+    }
+    let first_line = |result: Result<String, String>| match result {
+        Ok(value) => Ok(value),
+        Err(err) => Err(err.lines().next().unwrap_or_default().to_string()),
+    };
+    let (direct, via_print) = (first_line(eval_str(source)), first_line(eval_str(&printed)));
+    if direct != via_print {
+        return Err(format!(
+            "{}: evaluates to {:?}, but its printed form {} evaluates to {:?}",
+            source, direct, printed, via_print
+        ));
+    }
+    Ok(())
+}
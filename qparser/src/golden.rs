@@ -0,0 +1,105 @@
+//! Golden-file checks for rendered results and diagnostics.
+//!
+//! A golden case is a `.q` file of one input per line next to a `.out`
+//! file holding the transcript those inputs produce, rendered as the REPL
+//! prints it but without color. Checking a case compares the transcript
+//! against the `.out` file, so any change to an error message or its
+//! underline shows up as a diff. Set the [`BLESS_VAR`] environment
+//! variable to rewrite the `.out` files from the current output instead.
+
+use std::path::Path;
+
+use bumpalo::Bump;
+
+use crate::{Config, eval_str_in};
+
+/// Environment variable that makes [`check`] write golden files instead
+/// of comparing against them.
+pub const BLESS_VAR: &str = "QPARSER_BLESS";
+
+/// The transcript of evaluating each non-blank line of `source`: the
+/// input after a `>> ` prompt, then `=> ` and the result, or the error.
+pub fn transcript(source: &str, config: &Config) -> String {
+    let mut arena = Bump::new();
+    let mut out = String::new();
+    for input in source.lines().filter(|l| !l.trim().is_empty()) {
+        out.push_str(&format!(">> {}\n", input));
+        match eval_str_in(&arena, input, config) {
+            Ok(value) => out.push_str(&format!("=> {}\n", value)),
+            Err(err) => out.push_str(&format!("Error: {}\n", err)),
+        }
+        arena.reset();
+    }
+    out
+}
+
+/// Compare `actual` with the golden file at `path`, describing the first
+/// difference. When [`BLESS_VAR`] is set, write `actual` to `path` instead.
+pub fn check(path: &Path, actual: &str) -> Result<(), String> {
+    if std::env::var_os(BLESS_VAR).is_some() {
+        return std::fs::write(path, actual).map_err(|e| format!("{}: {}", path.display(), e));
+    }
+    let expected = std::fs::read_to_string(path).map_err(|e| {
+        format!(
+            "{}: {} (set {}=1 to create it)",
+            path.display(),
+            e,
+            BLESS_VAR
+        )
+    })?;
+    match first_difference(&expected, actual) {
+        None => Ok(()),
+        Some((line, e, a)) => Err(format!(
+            "{}:{}: golden output differs (set {}=1 to accept)\n  expected: {}\n    actual: {}",
+            path.display(),
+            line,
+            BLESS_VAR,
+            e.unwrap_or("<end of file>"),
+            a.unwrap_or("<end of output>")
+        )),
+    }
+}
+
+/// The 1-based number of the first line where `expected` and `actual`
+/// differ, with that line of each (`None` past the end), or `None` if
+/// they are the same.
+pub fn first_difference<'t>(
+    expected: &'t str,
+    actual: &'t str,
+) -> Option<(usize, Option<&'t str>, Option<&'t str>)> {
+    if expected == actual {
+        return None;
+    }
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (e, a) if e == a && e.is_some() => line += 1,
+            (e, a) => return Some((line, e, a)),
+        }
+    }
+}
+
+/// Check every `.q` case in `dir` against the `.out` file beside it,
+/// returning how many cases passed or every failure.
+pub fn check_dir(dir: &Path, config: &Config) -> Result<usize, String> {
+    let mut cases: Vec<_> = std::fs::read_dir(dir)
+        .map_err(|e| format!("{}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "q"))
+        .collect();
+    cases.sort();
+    let mut failures = Vec::new();
+    for case in &cases {
+        let source = std::fs::read_to_string(case).map_err(|e| e.to_string())?;
+        if let Err(failure) = check(&case.with_extension("out"), &transcript(&source, config)) {
+            failures.push(failure);
+        }
+    }
+    if failures.is_empty() {
+        Ok(cases.len())
+    } else {
+        Err(failures.join("\n"))
+    }
+}
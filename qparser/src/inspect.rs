@@ -0,0 +1,104 @@
+//! Shape and size of an expression and its value, for the REPL's
+//! `:inspect` view.
+//!
+//! Sizes are estimates: every tree node is counted as one [`Spanned`] in
+//! the arena, and an atom's heap footprint is whatever it owns outside
+//! its node (only big integers own any).
+
+use std::fmt;
+
+use bumpalo::Bump;
+
+use crate::ast::{Expr, Spanned};
+use crate::{Config, parser};
+
+/// What [`inspect`] found out about one input.
+#[derive(Debug, PartialEq)]
+pub struct Inspection {
+    /// Type name of the result, e.g. "int"
+    pub kind: &'static str,
+    /// Nodes in the parsed expression tree
+    pub nodes: usize,
+    /// Levels in the parsed expression tree, counting the root as 1
+    pub depth: usize,
+    /// Estimated arena bytes taken by the parsed tree
+    pub tree_bytes: usize,
+    /// Estimated bytes taken by the result, inline and on the heap
+    pub value_bytes: usize,
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "type {}, {} node{}, depth {}, ~{} B parsed, ~{} B value",
+            self.kind,
+            self.nodes,
+            if self.nodes == 1 { "" } else { "s" },
+            self.depth,
+            self.tree_bytes,
+            self.value_bytes
+        )
+    }
+}
+
+/// Parse and evaluate `input`, describing its expression tree and result.
+pub fn inspect(arena: &Bump, input: &str, config: &Config) -> Result<Inspection, String> {
+    let tree = parser::parse_expr(arena, input, config)?;
+    let value = tree.eval_with(config).map_err(|err| err.render(input))?;
+    let (nodes, depth) = shape(&tree);
+    Ok(Inspection {
+        kind: type_name(&value),
+        nodes,
+        depth,
+        tree_bytes: nodes * size_of::<Spanned>(),
+        value_bytes: size_of::<Expr>() + heap_bytes(&value),
+    })
+}
+
+/// Node count and depth of the tree rooted at `expr`.
+fn shape(expr: &Expr<'_>) -> (usize, usize) {
+    let children: &[&Spanned] = match expr {
+        Expr::Abs(x) | Expr::Arg(x) | Expr::Conj(x) | Expr::Numerator(x) | Expr::Denominator(x) => {
+            &[x]
+        }
+        Expr::Add(l, r)
+        | Expr::Sub(l, r)
+        | Expr::Mul(l, r)
+        | Expr::Div(l, r)
+        | Expr::Lt(l, r)
+        | Expr::Gt(l, r)
+        | Expr::Le(l, r)
+        | Expr::Ge(l, r)
+        | Expr::Eq(l, r)
+        | Expr::Ne(l, r)
+        | Expr::Max(l, r)
+        | Expr::Min(l, r) => &[l, r],
+        _ => &[],
+    };
+    children.iter().fold((1, 1), |(nodes, depth), child| {
+        let (child_nodes, child_depth) = shape(child);
+        (nodes + child_nodes, depth.max(child_depth + 1))
+    })
+}
+
+/// Bytes an atom owns outside its own node.
+fn heap_bytes(value: &Expr<'_>) -> usize {
+    match value {
+        Expr::BigInt(i) => i.bits().div_ceil(64) as usize * 8,
+        _ => 0,
+    }
+}
+
+/// Type name of an atom, without the article [`Expr::describe`] adds.
+fn type_name(value: &Expr<'_>) -> &'static str {
+    match value {
+        Expr::Int(_) => "int",
+        Expr::BigInt(_) => "big int",
+        Expr::Float(_) => "float",
+        Expr::Rational(_) => "rational",
+        Expr::Complex(_) => "complex",
+        Expr::Bool(_) => "boolean",
+        _ => "expression",
+    }
+}
@@ -1,31 +1,132 @@
 // Library root for Q parser crate
 
+pub mod arith;
 pub mod ast;
+pub mod error;
+pub mod generate;
+pub mod golden;
+pub mod inspect;
+pub mod ops;
 pub mod parser;
 
-/// Parse a Q expression from the input string.
-/// Returns the AST on success, or a parse error.
-/// Parse a Q expression from the input string.
-/// Returns the AST on success, or a stringified error.
-use crate::ast::Expr;
+use std::cell::Cell;
 
-/// Parse a Q expression from the input string.
-/// Returns the AST on success, or a stringified error.
-/// Parse a Q expression from the input string.
+use crate::ast::{Expr, Spanned};
+use crate::error::EvalError;
+use bumpalo::Bump;
+
+/// Settings applied while parsing and evaluating Q expressions.
+///
+/// Both depth limits turn input that would otherwise overflow the stack into a
+/// "Too deep" error. The defaults leave headroom on a 2 MiB thread stack.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Maximum parenthesis nesting accepted by the parser.
+    pub max_nesting: usize,
+    /// Maximum nesting of right and monadic operands during evaluation;
+    /// a flat chain like `1+2+3` is one level however long it is.
+    pub max_eval_depth: usize,
+    /// Divide ints exactly, producing rationals (`10/4` is `5/2`)
+    /// instead of floats.
+    pub exact: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_nesting: 32,
+            max_eval_depth: 256,
+            exact: false,
+        }
+    }
+}
+
+/// Callbacks made while an expression tree is evaluated (see
+/// [`ast::Expr::eval_hooked`]), for profilers, tracers and debuggers.
+///
+/// Hooks take `&self`, like [`EvalStats`]; keep any state in cells.
+pub trait EvalHook {
+    /// Called before `expr`, `depth` levels below the root, is evaluated.
+    fn enter(&self, _expr: &Expr<'_>, _depth: usize) {}
+
+    /// Called once `expr` has evaluated to `result`.
+    fn exit(&self, _expr: &Expr<'_>, _depth: usize, _result: &Result<Expr<'_>, EvalError>) {}
+}
+
+/// Work done by one evaluation (see [`ast::Expr::eval_counted`]).
+#[derive(Debug, Default)]
+pub struct EvalStats {
+    steps: Cell<usize>,
+    max_depth: Cell<usize>,
+}
+
+impl EvalHook for EvalStats {
+    fn enter(&self, _expr: &Expr<'_>, depth: usize) {
+        self.steps.set(self.steps.get() + 1);
+        self.max_depth.set(self.max_depth.get().max(depth + 1));
+    }
+}
+
+impl EvalStats {
+    /// Expression nodes evaluated.
+    pub fn steps(&self) -> usize {
+        self.steps.get()
+    }
+
+    /// Deepest level of the expression tree reached, counting the root
+    /// as 1.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth.get()
+    }
+}
+
+/// Parse a Q expression from the input string, allocating the AST in `arena`.
 /// Returns the AST on success, or a stringified list of parse errors.
-pub fn parse(input: &str) -> Result<Expr, String> {
-    parser::parse_expr(input)
+pub fn parse<'a>(arena: &'a Bump, input: &str) -> Result<Spanned<'a>, String> {
+    parser::parse_expr(arena, input, &Config::default())
 }
 
 /// Parse and evaluate a Q expression.
 /// Allocates AST in a bump arena that is dropped immediately.
 /// Returns formatted result or error.
 pub fn eval_str(input: &str) -> Result<String, String> {
-    let expr = parse(input)?;
-    match expr.eval() {
-        Ok(res) => Ok(res.to_string()),
-        Err(err) => Err(err),
-    }
+    eval_str_in(&Bump::new(), input, &Config::default())
+}
+
+/// Parse and evaluate a Q expression using a caller-provided arena and limits.
+/// Evaluation errors that point at an operand are rendered with the input
+/// and a `^^^` underline beneath that operand.
+/// Callers evaluating many inputs can `reset` one arena between them
+/// instead of allocating a fresh one each time.
+pub fn eval_str_in(arena: &Bump, input: &str, config: &Config) -> Result<String, String> {
+    eval_in(arena, input, config).map(|res| res.to_string())
+}
+
+/// Like [`eval_str_in`], but returns the resulting atom itself.
+pub fn eval_in<'a>(arena: &'a Bump, input: &str, config: &Config) -> Result<Expr<'a>, String> {
+    eval_counted_in(arena, input, config, &EvalStats::default())
+}
+
+/// Like [`eval_in`], also counting the evaluation's work in `stats`.
+pub fn eval_counted_in<'a>(
+    arena: &'a Bump,
+    input: &str,
+    config: &Config,
+    stats: &EvalStats,
+) -> Result<Expr<'a>, String> {
+    eval_hooked_in(arena, input, config, stats)
+}
+
+/// Like [`eval_in`], calling `hook` as each node is evaluated.
+pub fn eval_hooked_in<'a>(
+    arena: &'a Bump,
+    input: &str,
+    config: &Config,
+    hook: &dyn EvalHook,
+) -> Result<Expr<'a>, String> {
+    let expr = parser::parse_expr(arena, input, config)?;
+    expr.eval_hooked(config, hook)
+        .map_err(|err| err.render(input))
 }
 
 #[cfg(test)]
@@ -0,0 +1,199 @@
+//! Registry of the verbs (operators) understood by the parser.
+//!
+//! Each entry documents an operator's name, arity, accepted operand types
+//! and examples. The REPL's `:help` and the evaluator's type errors both
+//! read from this table, so it is the one place to describe a new verb.
+
+/// Description of one operator.
+#[derive(Debug)]
+pub struct OpInfo {
+    /// Symbol as written in source, e.g. `"<="`
+    pub symbol: &'static str,
+    /// Unicode spellings the parser also accepts, e.g. `"≤"`
+    pub aliases: &'static [&'static str],
+    /// Short name, e.g. `"less or equal"`
+    pub name: &'static str,
+    /// Number of operands
+    pub arity: usize,
+    /// Accepted operand types, as shown in diagnostics
+    pub operands: &'static str,
+    /// One-line description
+    pub doc: &'static str,
+    /// `(input, result)` pairs; checked by the test suite
+    pub examples: &'static [(&'static str, &'static str)],
+}
+
+/// All operators, loosest-binding last.
+pub const OPERATORS: &[OpInfo] = &[
+    OpInfo {
+        symbol: "abs",
+        aliases: &[],
+        name: "absolute value",
+        arity: 1,
+        operands: "numbers",
+        doc: "Absolute value; for a complex number, its magnitude as a float.",
+        examples: &[("abs(2-5)", "3"), ("abs(3+4i)", "5")],
+    },
+    OpInfo {
+        symbol: "arg",
+        aliases: &[],
+        name: "phase angle",
+        arity: 1,
+        operands: "numbers",
+        doc: "Angle of a complex number in radians; 0 for non-negative reals, π for negative ones.",
+        examples: &[
+            ("arg(1i)", "1.5707963267948966"),
+            ("arg(0-2)", "3.141592653589793"),
+        ],
+    },
+    OpInfo {
+        symbol: "conj",
+        aliases: &[],
+        name: "conjugate",
+        arity: 1,
+        operands: "numbers",
+        doc: "Complex conjugate; a real number is its own conjugate.",
+        examples: &[("conj(3+4i)", "3-4i"), ("conj 2", "2")],
+    },
+    OpInfo {
+        symbol: "numerator",
+        aliases: &[],
+        name: "numerator",
+        arity: 1,
+        operands: "ints or rationals",
+        doc: "Numerator of a rational in lowest terms (`numerator(6/4)` is `3` in exact mode); an int is its own numerator.",
+        examples: &[("numerator 7", "7"), ("numerator(0-3)", "-3")],
+    },
+    OpInfo {
+        symbol: "denominator",
+        aliases: &[],
+        name: "denominator",
+        arity: 1,
+        operands: "ints or rationals",
+        doc: "Positive denominator of a rational in lowest terms (`denominator(6/4)` is `2` in exact mode); 1 for an int.",
+        examples: &[("denominator 7", "1"), ("denominator(0-3)", "1")],
+    },
+    OpInfo {
+        symbol: "*",
+        aliases: &["×"],
+        name: "multiply",
+        arity: 2,
+        operands: "numbers",
+        doc: "Multiply two numbers; int * int stays int, otherwise the wider type of rational, float, complex.",
+        examples: &[("6*7", "42"), ("2*1.5", "3")],
+    },
+    OpInfo {
+        symbol: "/",
+        aliases: &["÷"],
+        name: "divide",
+        arity: 2,
+        operands: "numbers",
+        doc: "Divide two numbers; int / int is a float, or an exact rational in exact mode. Dividing by zero is an error.",
+        examples: &[("7/2", "3.5")],
+    },
+    OpInfo {
+        symbol: "+",
+        aliases: &[],
+        name: "add",
+        arity: 2,
+        operands: "numbers",
+        doc: "Add two numbers; int + int stays int, otherwise the wider type of rational, float, complex.",
+        examples: &[("1+2", "3"), ("1+0.5", "1.5")],
+    },
+    OpInfo {
+        symbol: "-",
+        aliases: &["−"],
+        name: "subtract",
+        arity: 2,
+        operands: "numbers",
+        doc: "Subtract two numbers; int - int stays int, otherwise the wider type of rational, float, complex.",
+        examples: &[("5-7", "-2")],
+    },
+    OpInfo {
+        symbol: "<",
+        aliases: &[],
+        name: "less than",
+        arity: 2,
+        operands: "atoms",
+        doc: "1b if the left atom sorts before the right one.",
+        examples: &[("1<2", "1b")],
+    },
+    OpInfo {
+        symbol: ">",
+        aliases: &[],
+        name: "greater than",
+        arity: 2,
+        operands: "atoms",
+        doc: "1b if the left atom sorts after the right one.",
+        examples: &[("1>2", "0b")],
+    },
+    OpInfo {
+        symbol: "<=",
+        aliases: &["≤"],
+        name: "less or equal",
+        arity: 2,
+        operands: "atoms",
+        doc: "1b unless the left atom sorts after the right one.",
+        examples: &[("2<=2", "1b")],
+    },
+    OpInfo {
+        symbol: ">=",
+        aliases: &["≥"],
+        name: "greater or equal",
+        arity: 2,
+        operands: "atoms",
+        doc: "1b unless the left atom sorts before the right one.",
+        examples: &[("1>=2", "0b")],
+    },
+    OpInfo {
+        symbol: "=",
+        aliases: &[],
+        name: "equal",
+        arity: 2,
+        operands: "atoms",
+        doc: "1b if both atoms are equal; ints and floats compare by value.",
+        examples: &[("1=1.0", "1b")],
+    },
+    OpInfo {
+        symbol: "<>",
+        aliases: &["≠"],
+        name: "not equal",
+        arity: 2,
+        operands: "atoms",
+        doc: "1b if the atoms differ.",
+        examples: &[("1<>2", "1b")],
+    },
+    OpInfo {
+        symbol: "&",
+        aliases: &["∧"],
+        name: "min",
+        arity: 2,
+        operands: "atoms",
+        doc: "The lesser of two atoms.",
+        examples: &[("3&5", "3")],
+    },
+    OpInfo {
+        symbol: "|",
+        aliases: &["∨"],
+        name: "max",
+        arity: 2,
+        operands: "atoms",
+        doc: "The greater of two atoms.",
+        examples: &[("3|5", "5")],
+    },
+];
+
+/// Look up an operator by its symbol or one of its aliases.
+pub fn lookup(symbol: &str) -> Option<&'static OpInfo> {
+    OPERATORS
+        .iter()
+        .find(|op| op.symbol == symbol || op.aliases.contains(&symbol))
+}
+
+/// Type error for `symbol`, naming the operand types it accepts.
+pub fn type_error(symbol: &str) -> String {
+    match lookup(symbol) {
+        Some(op) => format!("Type error: `{}` expects {}", op.symbol, op.operands),
+        None => format!("Type error in `{}`", symbol),
+    }
+}
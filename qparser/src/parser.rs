@@ -1,71 +1,303 @@
-use crate::ast::Expr;
+use crate::Config;
+use crate::ast::{Expr, Span, Spanned};
+use crate::error::EvalError;
+use bumpalo::Bump;
+use chumsky::error::SimpleReason;
 use chumsky::prelude::*;
-use chumsky::text::{digits, int};
+use chumsky::text::{digits, int, keyword};
+use num_complex::Complex64;
+
+/// Constructor of a monadic verb node, e.g. `Expr::Abs`.
+type Monad<'a> = fn(&'a Spanned<'a>) -> Expr<'a>;
+
+/// Build a parser for Q expressions (ints, floats, imaginary literals,
+/// arithmetic, comparisons, `|`/`&` max/min, the monadic `abs`/`arg`/`conj`
+/// and `numerator`/`denominator` verbs, parentheses).
+/// Child nodes are allocated in `arena`.
+pub fn expr_parser<'a>(
+    arena: &'a Bump,
+) -> impl Parser<char, Spanned<'a>, Error = Simple<char>> + 'a {
+    recursive(move |expr| {
+        // Imaginary literal: an int or float immediately followed by `i`
+        let imaginary = int(10)
+            .then(just('.').ignore_then(digits(10)).or_not())
+            .then_ignore(just('i'))
+            .map_with_span(|(int_part, frac): (String, Option<String>), span| {
+                let im = match frac {
+                    Some(frac) => format!("{}.{}", int_part, frac),
+                    None => int_part,
+                };
+                Spanned {
+                    expr: Expr::Complex(Complex64::new(0.0, im.parse().unwrap())),
+                    span,
+                }
+            })
+            .padded();
 
-/// Build a parser for Q expressions (ints, floats, +, -, *, /, parentheses).
-pub fn expr_parser() -> impl Parser<char, Expr, Error = Simple<char>> {
-    recursive(|expr| {
         // Floating-point: digits.digits
         let float = int(10)
             .then_ignore(just('.'))
             .then(digits(10))
-            .map(|(int_part, frac): (String, String)| {
-                Expr::Float(format!("{}.{}", int_part, frac).parse().unwrap())
+            .map_with_span(|(int_part, frac): (String, String), span| Spanned {
+                expr: Expr::Float(format!("{}.{}", int_part, frac).parse().unwrap()),
+                span,
             })
             .padded();
 
         // Integer literal
         let integer = int(10)
-            .map(|s: String| Expr::Int(s.parse().unwrap()))
+            // Report an out-of-range literal without abandoning the parse,
+            // so the error is not lost among the alternatives tried here
+            .validate(|s: String, span: Span, emit| {
+                if s.parse::<i64>().is_err() {
+                    emit(Simple::custom(
+                        span,
+                        format!("Integer literal `{}` exceeds 64-bit range", s),
+                    ));
+                }
+                s
+            })
+            .map_with_span(|s, span| Spanned {
+                expr: Expr::Int(s.parse().unwrap_or_default()),
+                span,
+            })
             .padded();
 
-        // Parenthesized expression
+        // Parenthesized expression; its span includes the parentheses
         let parenthesized = just('(')
-            .padded()
             .ignore_then(expr.clone())
-            .then_ignore(just(')').padded());
+            .then_ignore(just(')'))
+            .map_with_span(|inner: Spanned<'a>, span| Spanned {
+                expr: inner.expr,
+                span,
+            })
+            .padded();
 
-        // Atom: float, integer, or parenthesized
-        let atom = float.or(integer).or(parenthesized);
+        // Literal or parenthesized expression
+        let operand = imaginary.or(float).or(integer).or(parenthesized);
 
-        // * or / operator parser yielding the operator char
-        // * or / operator parser yielding the operator char
-        let op_mul_div = just('*').or(just('/')).padded();
+        // Monadic verb name, with its span
+        let monad = keyword("abs")
+            .to(Expr::Abs as Monad<'a>)
+            .or(keyword("arg").to(Expr::Arg as Monad<'a>))
+            .or(keyword("conj").to(Expr::Conj as Monad<'a>))
+            .or(keyword("numerator").to(Expr::Numerator as Monad<'a>))
+            .or(keyword("denominator").to(Expr::Denominator as Monad<'a>))
+            .map_with_span(|node, span: std::ops::Range<usize>| (node, span))
+            .padded();
+
+        // Atom: an operand with any number of monadic verbs applied to it,
+        // binding tighter than every dyadic verb (`abs 3-4i` is `(abs 3)-4i`)
+        let atom = monad
+            .repeated()
+            .then(operand)
+            .foldr(move |(node, span), x: Spanned<'a>| {
+                let span = span.start..x.span.end;
+                Spanned {
+                    expr: node(arena.alloc(x)),
+                    span,
+                }
+            })
+            // Boxed to keep the combinator type (and compile time) small
+            .boxed();
+
+        // * or / operator parser yielding the operator char; the Unicode
+        // aliases in `ops::OPERATORS` yield their ASCII symbol
+        let op_mul_div = just('*')
+            .or(just('/'))
+            .or(just('×').to('*'))
+            .or(just('÷').to('/'))
+            .padded();
 
         // Product: left-associative multiplication/division
         let product = atom
             .clone()
             .then(op_mul_div.then(atom.clone()).repeated())
-            .foldl(|l, (op, r)| match op {
-                '*' => Expr::Mul(Box::new(l), Box::new(r)),
-                '/' => Expr::Div(Box::new(l), Box::new(r)),
+            .foldl(move |l, (op, r)| match op {
+                '*' => binary(arena, l, r, Expr::Mul),
+                '/' => binary(arena, l, r, Expr::Div),
                 _ => unreachable!(),
             });
 
         // + or - operator parser yielding the operator char
-        let op_add_sub = just('+').or(just('-')).padded();
+        let op_add_sub = just('+').or(just('-')).or(just('−').to('-')).padded();
 
         // Sum: left-associative addition/subtraction
-        product
+        let sum = product
             .clone()
             .then(op_add_sub.then(product.clone()).repeated())
-            .foldl(|l, (op, r)| match op {
-                '+' => Expr::Add(Box::new(l), Box::new(r)),
-                '-' => Expr::Sub(Box::new(l), Box::new(r)),
+            .foldl(move |l, (op, r)| match op {
+                '+' => binary(arena, l, r, Expr::Add),
+                '-' => binary(arena, l, r, Expr::Sub),
+                _ => unreachable!(),
+            });
+
+        // Comparison operator parser; two-character operators are tried first
+        let op_cmp = just("<=")
+            .or(just(">="))
+            .or(just("<>"))
+            .or(just("<"))
+            .or(just(">"))
+            .or(just("="))
+            .or(just("≤").to("<="))
+            .or(just("≥").to(">="))
+            .or(just("≠").to("<>"))
+            .padded();
+
+        // Comparison: left-associative, binds looser than arithmetic
+        let comparison = sum.clone().then(op_cmp.then(sum.clone()).repeated()).foldl(
+            move |l, (op, r)| match op {
+                "<" => binary(arena, l, r, Expr::Lt),
+                ">" => binary(arena, l, r, Expr::Gt),
+                "<=" => binary(arena, l, r, Expr::Le),
+                ">=" => binary(arena, l, r, Expr::Ge),
+                "=" => binary(arena, l, r, Expr::Eq),
+                "<>" => binary(arena, l, r, Expr::Ne),
+                _ => unreachable!(),
+            },
+        );
+
+        // | (max) or & (min) operator parser yielding the operator char
+        let op_max_min = just('|')
+            .or(just('&'))
+            .or(just('∨').to('|'))
+            .or(just('∧').to('&'))
+            .padded();
+
+        // Max/min: left-associative, loosest binding
+        comparison
+            .clone()
+            .then(op_max_min.then(comparison.clone()).repeated())
+            .foldl(move |l, (op, r)| match op {
+                '|' => binary(arena, l, r, Expr::Max),
+                '&' => binary(arena, l, r, Expr::Min),
                 _ => unreachable!(),
             })
     })
 }
 
+/// Combine two operands into a binary node spanning both.
+fn binary<'a>(
+    arena: &'a Bump,
+    l: Spanned<'a>,
+    r: Spanned<'a>,
+    node: fn(&'a Spanned<'a>, &'a Spanned<'a>) -> Expr<'a>,
+) -> Spanned<'a> {
+    let span = l.span.start..r.span.end;
+    Spanned {
+        expr: node(arena.alloc(l), arena.alloc(r)),
+        span,
+    }
+}
+
 // (a top-level `parse` function is provided in lib.rs)
 
-/// Parse a Q expression from the input string.
-/// Returns the AST on success, or a stringified error.
-pub fn parse_expr(input: &str) -> Result<Expr, String> {
-    expr_parser().parse(input).map_err(|errs| {
-        errs.into_iter()
-            .map(|e| e.to_string())
-            .collect::<Vec<_>>()
-            .join(", ")
-    })
+/// Reject input whose parentheses nest deeper than `max_nesting`.
+/// Runs before the recursive-descent parser so pathological input
+/// cannot exhaust the stack.
+pub fn check_nesting(input: &str, max_nesting: usize) -> Result<(), String> {
+    let mut depth = 0usize;
+    for c in input.chars() {
+        match c {
+            '(' => {
+                depth += 1;
+                if depth > max_nesting {
+                    return Err(format!(
+                        "Too deep: parentheses nest more than {} levels",
+                        max_nesting
+                    ));
+                }
+            }
+            ')' => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Replace each `{- ... -}` block comment with spaces, so the parser
+/// never sees it. Comments nest, and newlines inside them are kept, so
+/// character offsets and line numbers still match `input`. Returns the
+/// span of the opening `{-` of a comment that is never closed.
+pub fn blank_comments(input: &str) -> Result<String, Span> {
+    let mut out = String::with_capacity(input.len());
+    // Character offsets of the `{-` of each open comment, innermost last
+    let mut open: Vec<usize> = Vec::new();
+    let mut chars = input.chars().enumerate().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, c)| c);
+        if (c, next) == ('{', Some('-')) {
+            chars.next();
+            open.push(i);
+            out.push_str("  ");
+        } else if (c, next) == ('-', Some('}')) && !open.is_empty() {
+            chars.next();
+            open.pop();
+            out.push_str("  ");
+        } else if open.is_empty() || c == '\n' {
+            out.push(c);
+        } else {
+            out.push(' ');
+        }
+    }
+    match open.first() {
+        Some(&start) => Err(start..start + 2),
+        None => Ok(out),
+    }
+}
+
+/// Render a parse error like chumsky's `Display`, but with the expected
+/// tokens sorted (end of input last) so identical errors render identically,
+/// and without the Unicode operator aliases.
+pub fn format_error(err: &Simple<char>) -> String {
+    if let SimpleReason::Custom(message) = err.reason() {
+        return message.clone();
+    }
+    let found = match err.found() {
+        Some(c) => format!("found '{}'", c),
+        None => "found end of input".to_string(),
+    };
+    // Unicode operator aliases are left out; their ASCII spellings are listed
+    let mut expected: Vec<&Option<char>> = err
+        .expected()
+        .filter(|e| e.is_none_or(|c| c.is_ascii()))
+        .collect();
+    expected.sort_by_key(|e| (e.is_none(), **e));
+    let expected: Vec<String> = expected
+        .into_iter()
+        .map(|e| match e {
+            Some(c) => c.to_string(),
+            None => "end of input".to_string(),
+        })
+        .collect();
+    match expected.len() {
+        0 => found,
+        1 => format!("{} but {} was expected", found, expected[0]),
+        _ => format!("{} but one of {} was expected", found, expected.join(", ")),
+    }
+}
+
+/// Parse a Q expression from the input string, allocating the AST in `arena`.
+/// The whole input must be consumed. Returns the AST on success, or the
+/// distinct parse errors joined into one string.
+pub fn parse_expr<'a>(
+    arena: &'a Bump,
+    input: &str,
+    config: &Config,
+) -> Result<Spanned<'a>, String> {
+    let code = blank_comments(input)
+        .map_err(|span| EvalError::at("Unterminated block comment", span).render(input))?;
+    check_nesting(&code, config.max_nesting)?;
+    expr_parser(arena)
+        .then_ignore(end())
+        .parse(code.as_str())
+        .map_err(|errs| {
+            let mut messages: Vec<String> = Vec::new();
+            for message in errs.iter().map(format_error) {
+                if !messages.contains(&message) {
+                    messages.push(message);
+                }
+            }
+            messages.join(", ")
+        })
 }
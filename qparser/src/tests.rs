@@ -25,3 +25,484 @@ fn test_eval_div_float() {
     let out = eval_str("7/2").unwrap();
     assert_eq!(out, "3.5");
 }
+
+#[test]
+fn test_eval_comparisons() {
+    assert_eq!(eval_str("1<2").unwrap(), "1b");
+    assert_eq!(eval_str("2>=3").unwrap(), "0b");
+    assert_eq!(eval_str("1+2<=3").unwrap(), "1b");
+    assert_eq!(eval_str("2=2.0").unwrap(), "1b");
+    assert_eq!(eval_str("2<>2.5").unwrap(), "1b");
+}
+
+#[test]
+fn test_eval_max_min() {
+    assert_eq!(eval_str("3|5").unwrap(), "5");
+    assert_eq!(eval_str("3&5.5").unwrap(), "3");
+    assert_eq!(eval_str("(1<2)|0").unwrap(), "0");
+}
+
+#[test]
+fn test_compare_orders_bools_before_numbers() {
+    use crate::ast::Expr;
+    use std::cmp::Ordering;
+    assert_eq!(
+        Expr::Bool(true).compare(&Expr::Int(-5)),
+        Some(Ordering::Less)
+    );
+    assert_eq!(
+        Expr::Int(2).compare(&Expr::Float(1.5)),
+        Some(Ordering::Greater)
+    );
+    assert_eq!(
+        Expr::Float(-1.0).compare(&Expr::Int(-1)),
+        Some(Ordering::Equal)
+    );
+}
+
+#[test]
+fn test_parse_allocates_in_arena() {
+    use crate::ast::{Expr, Spanned};
+    use crate::parse;
+    use bumpalo::Bump;
+    let arena = Bump::new();
+    let ast = parse(&arena, "1 + 2").unwrap();
+    let one = Spanned {
+        expr: Expr::Int(1),
+        span: 0..1,
+    };
+    let two = Spanned {
+        expr: Expr::Int(2),
+        span: 4..5,
+    };
+    assert_eq!(*ast, Expr::Add(&one, &two));
+    assert_eq!(ast.span, 0..5);
+    assert!(arena.allocated_bytes() > 0);
+}
+
+#[test]
+fn test_eval_float_equality_semantics() {
+    // Signed zeros are equal
+    assert_eq!(eval_str("0.0=0.0*(0-1)").unwrap(), "1b");
+    // A float overflowing to infinity; inf - inf is NaN, which equals itself
+    let inf = format!("1{}.0", "0".repeat(400));
+    let nan = format!("({inf}-{inf})");
+    assert_eq!(eval_str(&format!("{nan}={nan}")).unwrap(), "1b");
+    assert_eq!(eval_str(&format!("{nan}<0-{inf}")).unwrap(), "1b");
+    // Int/float comparison is exact beyond 2^53
+    assert_eq!(
+        eval_str("9007199254740993=9007199254740992.0").unwrap(),
+        "0b"
+    );
+}
+
+#[test]
+fn test_atom_key_consistent_with_compare() {
+    use crate::ast::Expr;
+    use num_rational::Ratio;
+    use std::cmp::Ordering;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let hash = |e: &Expr| {
+        let mut h = DefaultHasher::new();
+        e.key().unwrap().hash(&mut h);
+        h.finish()
+    };
+    let atoms = [
+        Expr::Bool(false),
+        Expr::Bool(true),
+        Expr::Int(0),
+        Expr::Int(1),
+        Expr::Int(-1),
+        Expr::Int(i64::MIN),
+        Expr::Int(i64::MAX),
+        Expr::Int(9_007_199_254_740_993),
+        Expr::Float(0.0),
+        Expr::Float(-0.0),
+        Expr::Float(1.0),
+        Expr::Float(1.5),
+        Expr::Float(-1.5),
+        Expr::Float(f64::NAN),
+        Expr::Float(-f64::NAN),
+        Expr::Float(f64::INFINITY),
+        Expr::Float(f64::NEG_INFINITY),
+        Expr::Float(9_223_372_036_854_775_808.0),
+        Expr::Float(-9_223_372_036_854_775_808.0),
+        Expr::Float(9_007_199_254_740_992.0),
+        Expr::Float(f64::MIN_POSITIVE),
+        Expr::Float(0.5),
+        Expr::Float(0.1),
+        Expr::Float(1e-300),
+        Expr::Float(2f64.powi(-62)),
+        Expr::Rational(Ratio::new(1, 2)),
+        Expr::Rational(Ratio::new(-1, 3)),
+        Expr::Rational(Ratio::new(1, 10_000_000_000)),
+        Expr::Rational(Ratio::new(1, 1 << 62)),
+    ];
+    for a in &atoms {
+        for b in &atoms {
+            let ord = a.compare(b).unwrap();
+            assert_eq!(ord, b.compare(a).unwrap().reverse(), "{a:?} vs {b:?}");
+            assert_eq!(ord == Ordering::Equal, a.key() == b.key(), "{a:?} vs {b:?}");
+            if ord == Ordering::Equal {
+                assert_eq!(hash(a), hash(b), "{a:?} vs {b:?}");
+            }
+            for c in &atoms {
+                if ord != Ordering::Greater && b.compare(c).unwrap() != Ordering::Greater {
+                    assert_ne!(a.compare(c), Some(Ordering::Greater), "{a:?} {b:?} {c:?}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_deep_parentheses_are_rejected() {
+    let deep = format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000));
+    let err = eval_str(&deep).unwrap_err();
+    assert!(err.starts_with("Too deep"), "{err}");
+    let ok = format!("{}1{}", "(".repeat(32), ")".repeat(32));
+    assert_eq!(eval_str(&ok).unwrap(), "1");
+}
+
+#[test]
+fn test_flat_chain_evaluates_but_deep_nesting_is_rejected() {
+    assert_eq!(eval_str(&vec!["1"; 10_000].join("+")).unwrap(), "10000");
+    let mixed = vec!["2*3-1<9"; 2_000].join("|");
+    assert_eq!(eval_str(&mixed).unwrap(), "1b");
+
+    // Right operands nest: 1+(1+(1+...)) within the parenthesis limit,
+    // and monadic verbs, which need no parentheses at all
+    let config = crate::Config {
+        max_eval_depth: 20,
+        ..crate::Config::default()
+    };
+    let arena = bumpalo::Bump::new();
+    let nested = format!("{}1{}", "(1+".repeat(30), ")".repeat(30));
+    let err = crate::eval_str_in(&arena, &nested, &config).unwrap_err();
+    assert!(err.starts_with("Too deep: expression nests"), "{err}");
+    let err = eval_str(&format!("{}1", "abs ".repeat(10_000))).unwrap_err();
+    assert!(err.starts_with("Too deep: expression nests"), "{err}");
+}
+
+#[test]
+fn test_fuzz_random_nesting_never_crashes() {
+    // Deterministic LCG so failures reproduce
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let pieces = ["(", ")", "1", "2.5", "+", "*", "<", "|", " "];
+    for _ in 0..500 {
+        let mut input = String::new();
+        for _ in 0..200 {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            input.push_str(pieces[(state >> 33) as usize % pieces.len()]);
+        }
+        // Any result is fine; the process must not overflow its stack
+        let _ = eval_str(&input);
+    }
+}
+
+#[test]
+fn test_trailing_input_is_an_error() {
+    let err = eval_str("1 2").unwrap_err();
+    assert_eq!(
+        err,
+        "found '2' but one of &, *, +, -, /, <, =, >, |, end of input was expected"
+    );
+    assert!(eval_str("3*(").is_err());
+}
+
+#[test]
+fn test_block_comments_are_ignored() {
+    assert_eq!(eval_str("1+{- two -}2").unwrap(), "3");
+    assert_eq!(eval_str("{- a {- nested -} comment -} 2*3").unwrap(), "6");
+    // Comments keep offsets, so errors still point into the input
+    assert_eq!(
+        eval_str("{- (((( -} 1/0").unwrap_err(),
+        "Division by zero\n  {- (((( -} 1/0\n               ^"
+    );
+    assert_eq!(
+        eval_str("1 {- a {- b -}").unwrap_err(),
+        "Unterminated block comment\n  1 {- a {- b -}\n    ^^"
+    );
+}
+
+#[test]
+fn test_operator_registry_examples() {
+    use crate::ops::OPERATORS;
+    for op in OPERATORS {
+        assert!(!op.examples.is_empty(), "{} has no examples", op.symbol);
+        for (input, expected) in op.examples {
+            assert!(
+                input.contains(op.symbol),
+                "{input} does not use {}",
+                op.symbol
+            );
+            assert_eq!(eval_str(input).unwrap(), *expected, "{input}");
+            for alias in op.aliases {
+                let input = input.replace(op.symbol, alias);
+                assert_eq!(eval_str(&input).unwrap(), *expected, "{input}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_type_error_points_at_offending_operand() {
+    assert_eq!(
+        eval_str("(1<2)+1").unwrap_err(),
+        "Type error: `+` expects numbers, but its left operand is a boolean\n  (1<2)+1\n  ^^^^^"
+    );
+    assert_eq!(
+        eval_str("2 * (3>1)").unwrap_err(),
+        "Type error: `*` expects numbers, but its right operand is a boolean\n  2 * (3>1)\n      ^^^^^"
+    );
+}
+
+#[test]
+fn test_ordering_error_points_at_offending_operand() {
+    assert_eq!(
+        eval_str("1 < 2i").unwrap_err(),
+        "Type error: `<` cannot order complex numbers\n  1 < 2i\n      ^^"
+    );
+    assert_eq!(
+        eval_str("(3i+1)>1").unwrap_err(),
+        "Type error: `>` cannot order complex numbers\n  (3i+1)>1\n  ^^^^^^"
+    );
+    assert_eq!(
+        eval_str("1 | 2i").unwrap_err(),
+        "Type error: `|` cannot order complex numbers\n  1 | 2i\n      ^^"
+    );
+    assert_eq!(
+        eval_str("abs(1i) & (2i*2)").unwrap_err(),
+        "Type error: `&` cannot order complex numbers\n  abs(1i) & (2i*2)\n            ^^^^^^"
+    );
+    assert_eq!(eval_str("1i = 1i").unwrap(), "1b");
+}
+
+#[test]
+fn test_division_by_zero_points_at_divisor() {
+    assert_eq!(
+        eval_str("10/(2-2)").unwrap_err(),
+        "Division by zero\n  10/(2-2)\n     ^^^^^"
+    );
+}
+
+#[test]
+fn test_exact_mode_keeps_rationals() {
+    use crate::{Config, eval_str_in};
+    use bumpalo::Bump;
+
+    let exact = Config {
+        exact: true,
+        ..Config::default()
+    };
+    for (input, expected) in [
+        ("10/4", "5/2"),
+        ("1/3+1/6", "1/2"),
+        ("(1/3)*3", "1"),
+        ("2-7/2", "-3/2"),
+        ("1/2=0.5", "1b"),
+        ("1/3<0.3333333333333333", "0b"),
+        ("1/2+0.25", "0.75"),
+    ] {
+        assert_eq!(
+            eval_str_in(&Bump::new(), input, &exact).unwrap(),
+            expected,
+            "{input}"
+        );
+    }
+    // |i64::MIN / 3| has no 64-bit numerator: an error, not a panic
+    assert_eq!(
+        eval_str_in(&Bump::new(), "abs((0-9223372036854775807-1)/3)", &exact).unwrap_err(),
+        "Overflow: rational `abs` exceeds 64-bit range"
+    );
+    assert_eq!(
+        eval_str_in(&Bump::new(), "abs((0-9223372036854775807)/3)", &exact).unwrap(),
+        "9223372036854775807/3"
+    );
+    assert!(
+        eval_str_in(&Bump::new(), "(1/9223372036854775807)/2", &exact)
+            .unwrap_err()
+            .starts_with("Overflow: rational `/`")
+    );
+    assert_eq!(eval_str("10/4").unwrap(), "2.5");
+}
+
+#[test]
+fn test_numerator_and_denominator() {
+    use crate::{Config, eval_str_in};
+    use bumpalo::Bump;
+
+    let exact = Config {
+        exact: true,
+        ..Config::default()
+    };
+    for (input, expected) in [
+        ("numerator 6/4", "3/2"),
+        ("numerator(6/4)", "3"),
+        ("denominator(6/4)", "2"),
+        ("denominator((0-6)/4)", "2"),
+        ("numerator((0-6)/4)", "-3"),
+        ("denominator(9223372036854775807+1)", "1"),
+        ("numerator(1/3)+denominator(1/3)", "4"),
+    ] {
+        assert_eq!(
+            eval_str_in(&Bump::new(), input, &exact).unwrap(),
+            expected,
+            "{input}"
+        );
+    }
+    assert_eq!(
+        eval_str("numerator(6/4)").unwrap_err(),
+        "Type error: `numerator` expects ints or rationals, but its operand is a float\n  numerator(6/4)\n           ^^^^^"
+    );
+    assert!(eval_str("denominator 2i").is_err());
+}
+
+#[test]
+fn test_int_overflow_promotes_to_big_int() {
+    for (input, expected) in [
+        ("9223372036854775807+1", "9223372036854775808"),
+        ("(9223372036854775807+1)-1", "9223372036854775807"),
+        (
+            "9223372036854775807*9223372036854775807",
+            "85070591730234615847396907784232501249",
+        ),
+        ("abs ((0-9223372036854775807)-1)", "9223372036854775808"),
+        ("(9223372036854775807+1)>9223372036854775807", "1b"),
+        ("(9223372036854775807+1)=9223372036854775808.0", "1b"),
+        (
+            "(9223372036854775807*2)|(9223372036854775807+1)",
+            "18446744073709551614",
+        ),
+        ("(9223372036854775807+1)/2", "4611686018427388000"),
+    ] {
+        assert_eq!(eval_str(input).unwrap(), expected, "{input}");
+    }
+    assert_eq!(
+        eval_str("99999999999999999999").unwrap_err(),
+        "Integer literal `99999999999999999999` exceeds 64-bit range"
+    );
+}
+
+#[test]
+fn test_complex_numbers() {
+    for (input, expected) in [
+        ("3+4i", "3+4i"),
+        ("1i*1i", "-1"),
+        ("(1+2i)*(3-1i)", "5+5i"),
+        ("(3+4i)-4i", "3"),
+        ("abs(3+4i)", "5"),
+        ("abs 2-5", "-3"),
+        ("conj(1-2i)", "1+2i"),
+        ("arg 1", "0"),
+        ("1+1i=1i+1", "1b"),
+        ("1i<>1", "1b"),
+    ] {
+        assert_eq!(eval_str(input).unwrap(), expected, "{input}");
+    }
+    assert_eq!(
+        eval_str("1i<2").unwrap_err(),
+        "Type error: `<` cannot order complex numbers\n  1i<2\n  ^^"
+    );
+    assert_eq!(
+        eval_str("1/0i").unwrap_err(),
+        "Division by zero\n  1/0i\n    ^^"
+    );
+    assert_eq!(
+        eval_str("abs (1<2)").unwrap_err(),
+        "Type error: `abs` expects numbers, but its operand is a boolean\n  abs (1<2)\n      ^^^^^"
+    );
+}
+
+#[test]
+fn test_generated_programs_round_trip() {
+    use crate::generate::{Generator, round_trip};
+    let mut generator = Generator::new(42);
+    for _ in 0..2000 {
+        let source = generator.expr(4);
+        if let Err(divergence) = round_trip(&source) {
+            panic!("{divergence}");
+        }
+    }
+}
+
+#[test]
+fn test_eval_stats_count_steps_and_depth() {
+    use crate::{Config, EvalStats, eval_counted_in};
+    let arena = bumpalo::Bump::new();
+    let stats = EvalStats::default();
+    eval_counted_in(&arena, "1+2*abs 3", &Config::default(), &stats).unwrap();
+    assert_eq!((stats.steps(), stats.max_depth()), (6, 4));
+
+    // Evaluation stops at the first error
+    let stats = EvalStats::default();
+    eval_counted_in(&arena, "(1/0)+2*3", &Config::default(), &stats).unwrap_err();
+    assert_eq!((stats.steps(), stats.max_depth()), (4, 3));
+}
+
+#[test]
+fn test_inspect_describes_tree_and_value() {
+    use crate::Config;
+    use crate::ast::{Expr, Spanned};
+    use crate::inspect::inspect;
+    let arena = bumpalo::Bump::new();
+    let found = inspect(&arena, "1+2*abs 3", &Config::default()).unwrap();
+    assert_eq!((found.kind, found.nodes, found.depth), ("int", 6, 4));
+    assert_eq!(found.tree_bytes, 6 * size_of::<Spanned>());
+    assert_eq!(found.value_bytes, size_of::<Expr>());
+
+    let found = inspect(
+        &arena,
+        "9223372036854775807*9223372036854775807",
+        &Config::default(),
+    )
+    .unwrap();
+    assert_eq!(found.kind, "big int");
+    assert_eq!(found.value_bytes, size_of::<Expr>() + 16);
+    assert!(inspect(&arena, "1/0", &Config::default()).is_err());
+}
+
+#[test]
+fn test_eval_hook_sees_entry_and_exit() {
+    use crate::ast::Expr;
+    use crate::error::EvalError;
+    use crate::{Config, EvalHook, eval_hooked_in};
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct Trace(RefCell<Vec<String>>);
+
+    impl EvalHook for Trace {
+        fn enter(&self, expr: &Expr<'_>, depth: usize) {
+            self.0.borrow_mut().push(format!("{}> {}", depth, expr));
+        }
+
+        fn exit(&self, _expr: &Expr<'_>, depth: usize, result: &Result<Expr<'_>, EvalError>) {
+            let shown = match result {
+                Ok(value) => value.to_string(),
+                Err(err) => err.to_string(),
+            };
+            self.0.borrow_mut().push(format!("{}< {}", depth, shown));
+        }
+    }
+
+    let trace = Trace::default();
+    eval_hooked_in(&bumpalo::Bump::new(), "1+2/0", &Config::default(), &trace).unwrap_err();
+    assert_eq!(
+        *trace.0.borrow(),
+        [
+            "0> (1 + (2 / 0))",
+            "1> 1",
+            "1< 1",
+            "1> (2 / 0)",
+            "2> 2",
+            "2< 2",
+            "2> 0",
+            "2< 0",
+            "1< Division by zero",
+            "0< Division by zero"
+        ]
+    );
+}
@@ -0,0 +1,30 @@
+use std::path::Path;
+
+use qparser::Config;
+use qparser::golden::{check_dir, first_difference, transcript};
+
+const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+
+#[test]
+fn test_golden_diagnostics() {
+    match check_dir(Path::new(GOLDEN), &Config::default()) {
+        Ok(cases) => assert!(cases > 0),
+        Err(failures) => panic!("{}", failures),
+    }
+}
+
+#[test]
+fn test_golden_mismatch_names_the_line() {
+    // Compared directly: `check` would overwrite the file when blessing
+    let expected = std::fs::read_to_string(Path::new(GOLDEN).join("errors.out")).unwrap();
+    let actual = transcript("1/0\n2/0\n", &Config::default());
+    assert_eq!(
+        first_difference(&expected, &actual),
+        Some((5, Some(">> 1+(2*"), Some(">> 2/0")))
+    );
+    assert_eq!(first_difference(&expected, &expected), None);
+    assert_eq!(
+        first_difference("a\nb\n", "a\n"),
+        Some((2, Some("b"), None))
+    );
+}
@@ -0,0 +1,69 @@
+use arrow2::datatypes::{DataType, Field, Schema};
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use qstorage::batch::{ArithOp, ColumnExpr, CompareOp, Value};
+use qstorage::{QStore, QStoreConfig, ScalarValue};
+
+/// A store of 100k rows of `ts, v`.
+fn store(dir: &std::path::Path) -> QStore {
+    let mut store = QStore::init(QStoreConfig {
+        schema: Schema {
+            fields: vec![
+                Field::new("ts", DataType::Int64, false),
+                Field::new("v", DataType::Float64, false),
+            ],
+            metadata: Default::default(),
+        },
+        data_dir: dir.to_path_buf(),
+    });
+    for i in 0..100_000 {
+        store.put(vec![
+            ScalarValue::Int64(i),
+            ScalarValue::Float64((i % 1000) as f64 / 10.0),
+        ]);
+    }
+    store
+}
+
+/// `v * 2 + 1 > 50 and ts >= 1000`
+fn predicate() -> ColumnExpr {
+    let scaled = ColumnExpr::Arith(
+        ArithOp::Add,
+        Box::new(ColumnExpr::Arith(
+            ArithOp::Mul,
+            Box::new(ColumnExpr::column("v")),
+            Box::new(ColumnExpr::Literal(ScalarValue::Float64(2.0))),
+        )),
+        Box::new(ColumnExpr::Literal(ScalarValue::Float64(1.0))),
+    );
+    ColumnExpr::And(
+        Box::new(ColumnExpr::Compare(
+            CompareOp::Gt,
+            Box::new(scaled),
+            Box::new(ColumnExpr::Literal(ScalarValue::Float64(50.0))),
+        )),
+        Box::new(ColumnExpr::Compare(
+            CompareOp::Ge,
+            Box::new(ColumnExpr::column("ts")),
+            Box::new(ColumnExpr::Literal(ScalarValue::Int64(1000))),
+        )),
+    )
+}
+
+fn bench_filter(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let store = store(dir.path());
+    let predicate = predicate();
+    c.bench_function("filter_batch", |b| {
+        b.iter(|| store.filter(black_box(&predicate)).unwrap())
+    });
+    c.bench_function("filter_row_at_a_time", |b| {
+        b.iter(|| {
+            (0..store.memtable_row_count())
+                .filter(|&row| predicate.eval_row(&store, row) == Ok(Value::Bool(true)))
+                .count()
+        })
+    });
+}
+
+criterion_group!(benches, bench_filter);
+criterion_main!(benches);
@@ -0,0 +1,322 @@
+//! Compression codecs for numeric columns.
+//!
+//! - Delta-of-delta for Int64 timestamps: regular intervals encode to
+//!   about one byte per value.
+//! - Gorilla XOR for Float64 gauges: slowly changing values share most
+//!   bits with their predecessor.
+//! - Run-length for Int64 columns with repeated values.
+//!
+//! Integers are written as zigzag LEB128 varints. Every encoding starts
+//! with the value count.
+
+use crate::{QStore, ScalarValue};
+
+/// A column encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// 8 little-endian bytes per value, as stored on disk
+    Raw,
+    DeltaOfDelta,
+    Gorilla,
+    RunLength,
+}
+
+/// Size of a column under its best codec.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressionStats {
+    pub column: String,
+    pub codec: Codec,
+    pub raw_bytes: usize,
+    pub encoded_bytes: usize,
+}
+
+impl CompressionStats {
+    /// Raw size divided by encoded size.
+    pub fn ratio(&self) -> f64 {
+        self.raw_bytes as f64 / self.encoded_bytes.max(1) as f64
+    }
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    ((n >> 1) as i64) ^ -((n & 1) as i64)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// Reads varints from a byte slice.
+struct Varints<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Varints<'_> {
+    fn next(&mut self) -> Result<u64, String> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.bytes.get(self.pos).ok_or("Truncated varint")?;
+            self.pos += 1;
+            n |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return Ok(n);
+            }
+        }
+        Err("Varint too long".to_string())
+    }
+
+    fn next_i64(&mut self) -> Result<i64, String> {
+        self.next().map(unzigzag)
+    }
+
+    fn done(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+}
+
+fn varints(bytes: &[u8]) -> Varints<'_> {
+    Varints { bytes, pos: 0 }
+}
+
+/// Delta-of-delta encode `values`.
+pub fn encode_delta_of_delta(values: &[i64]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, values.len() as u64);
+    let (mut prev, mut prev_delta) = (0i64, 0i64);
+    for &value in values {
+        let delta = value.wrapping_sub(prev);
+        write_varint(&mut out, zigzag(delta.wrapping_sub(prev_delta)));
+        (prev, prev_delta) = (value, delta);
+    }
+    out
+}
+
+pub fn decode_delta_of_delta(bytes: &[u8]) -> Result<Vec<i64>, String> {
+    let mut input = varints(bytes);
+    let count = input.next()? as usize;
+    let mut values = Vec::with_capacity(count.min(bytes.len()));
+    let (mut prev, mut prev_delta) = (0i64, 0i64);
+    for _ in 0..count {
+        let delta = prev_delta.wrapping_add(input.next_i64()?);
+        prev = prev.wrapping_add(delta);
+        prev_delta = delta;
+        values.push(prev);
+    }
+    if !input.done() {
+        return Err("Trailing bytes after delta-of-delta data".to_string());
+    }
+    Ok(values)
+}
+
+/// Run-length encode `values` as (value, run length) pairs.
+pub fn encode_run_length(values: &[i64]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, values.len() as u64);
+    for run in values.chunk_by(|a, b| a == b) {
+        write_varint(&mut out, zigzag(run[0]));
+        write_varint(&mut out, run.len() as u64);
+    }
+    out
+}
+
+pub fn decode_run_length(bytes: &[u8]) -> Result<Vec<i64>, String> {
+    let mut input = varints(bytes);
+    let count = input.next()? as usize;
+    let mut values = Vec::with_capacity(count.min(bytes.len()));
+    while !input.done() {
+        let value = input.next_i64()?;
+        let run = input.next()? as usize;
+        if values.len().checked_add(run).is_none_or(|end| end > count) {
+            return Err("Run-length data exceeds its count".to_string());
+        }
+        values.extend(std::iter::repeat_n(value, run));
+    }
+    if values.len() != count {
+        return Err("Truncated run-length data".to_string());
+    }
+    Ok(values)
+}
+
+/// Writes bit fields, most significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits used in the last byte (0 means a new byte is needed)
+    used: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            if self.used == 0 {
+                self.bytes.push(0);
+            }
+            let bit = (value >> i) & 1;
+            *self.bytes.last_mut().unwrap() |= (bit as u8) << (7 - self.used);
+            self.used = (self.used + 1) % 8;
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    /// Position in bits
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, bits: u32) -> Result<u64, String> {
+        let mut value = 0;
+        for _ in 0..bits {
+            let byte = self
+                .bytes
+                .get(self.pos / 8)
+                .ok_or("Truncated Gorilla data")?;
+            value = (value << 1) | u64::from((byte >> (7 - self.pos % 8)) & 1);
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// Gorilla XOR encode `values`: each value is stored as the meaningful
+/// bits of its XOR with the previous one, reusing the previous window of
+/// leading/trailing zeros when the new bits fit in it.
+pub fn encode_gorilla(values: &[f64]) -> Vec<u8> {
+    let mut header = Vec::new();
+    write_varint(&mut header, values.len() as u64);
+    let mut bits = BitWriter::default();
+    let mut prev = 0u64;
+    // (leading zeros, trailing zeros) of the last stored window
+    let mut window: Option<(u32, u32)> = None;
+    for (i, value) in values.iter().enumerate() {
+        let value = value.to_bits();
+        if i == 0 {
+            bits.write(value, 64);
+            prev = value;
+            continue;
+        }
+        let xor = value ^ prev;
+        prev = value;
+        if xor == 0 {
+            bits.write(0, 1);
+            continue;
+        }
+        bits.write(1, 1);
+        // Leading zeros are stored in 5 bits
+        let leading = xor.leading_zeros().min(31);
+        let trailing = xor.trailing_zeros();
+        match window {
+            Some((l, t)) if leading >= l && trailing >= t => {
+                bits.write(0, 1);
+                bits.write(xor >> t, 64 - l - t);
+            }
+            _ => {
+                let len = 64 - leading - trailing;
+                bits.write(1, 1);
+                bits.write(u64::from(leading), 5);
+                // A length of 64 is stored as 0
+                bits.write(u64::from(len % 64), 6);
+                bits.write(xor >> trailing, len);
+                window = Some((leading, trailing));
+            }
+        }
+    }
+    header.extend(bits.bytes);
+    header
+}
+
+pub fn decode_gorilla(bytes: &[u8]) -> Result<Vec<f64>, String> {
+    let mut input = varints(bytes);
+    let count = input.next()? as usize;
+    let mut bits = BitReader {
+        bytes: &bytes[input.pos..],
+        pos: 0,
+    };
+    let mut values = Vec::with_capacity(count.min(bytes.len() * 8));
+    let mut prev = 0u64;
+    let mut window = (0u32, 0u32);
+    for i in 0..count {
+        if i == 0 {
+            prev = bits.read(64)?;
+        } else if bits.read(1)? == 1 {
+            if bits.read(1)? == 1 {
+                let leading = bits.read(5)? as u32;
+                let len = match bits.read(6)? as u32 {
+                    0 => 64,
+                    len => len,
+                };
+                if leading + len > 64 {
+                    return Err("Corrupt Gorilla window".to_string());
+                }
+                window = (leading, 64 - leading - len);
+            }
+            let (l, t) = window;
+            prev ^= bits.read(64 - l - t)? << t;
+        }
+        values.push(f64::from_bits(prev));
+    }
+    Ok(values)
+}
+
+/// The codecs that apply to a column of `values`, with their encoded
+/// sizes, smallest first. Raw is always a candidate.
+pub fn candidates(values: &[ScalarValue]) -> Vec<(Codec, usize)> {
+    let mut sizes = vec![(Codec::Raw, values.len() * 8)];
+    match values.first() {
+        Some(ScalarValue::Int64(_)) => {
+            let ints: Vec<i64> = values
+                .iter()
+                .map(|v| match v {
+                    ScalarValue::Int64(i) => *i,
+                    ScalarValue::Float64(f) => *f as i64,
+                })
+                .collect();
+            sizes.push((Codec::DeltaOfDelta, encode_delta_of_delta(&ints).len()));
+            sizes.push((Codec::RunLength, encode_run_length(&ints).len()));
+        }
+        Some(ScalarValue::Float64(_)) => {
+            let floats: Vec<f64> = values
+                .iter()
+                .map(|v| match v {
+                    ScalarValue::Int64(i) => *i as f64,
+                    ScalarValue::Float64(f) => *f,
+                })
+                .collect();
+            sizes.push((Codec::Gorilla, encode_gorilla(&floats).len()));
+        }
+        None => (),
+    }
+    sizes.sort_by_key(|(_, size)| *size);
+    sizes
+}
+
+impl QStore {
+    /// The best codec for each column, in schema order, with its raw and
+    /// encoded sizes.
+    pub fn compression_stats(&self) -> Vec<CompressionStats> {
+        self.config
+            .schema
+            .fields
+            .iter()
+            .map(|field| {
+                let values = &self.columns[&field.name];
+                let (codec, encoded_bytes) = candidates(values)[0];
+                CompressionStats {
+                    column: field.name.clone(),
+                    codec,
+                    raw_bytes: values.len() * 8,
+                    encoded_bytes,
+                }
+            })
+            .collect()
+    }
+}
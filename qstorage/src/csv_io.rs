@@ -0,0 +1,87 @@
+//! CSV import and export for splayed tables.
+//!
+//! The header row names the columns. On import every schema column must
+//! appear exactly once (in any order) and no other columns are allowed;
+//! export writes the columns in schema order.
+
+use std::io::{Read, Write};
+
+use arrow2::datatypes::DataType;
+
+use crate::{QStore, ScalarValue};
+
+impl QStore {
+    /// Append the rows of a CSV document, returning how many were added.
+    ///
+    /// The whole input is validated before anything is written, so a bad
+    /// cell leaves the store unchanged. Errors name the offending line.
+    pub fn import_csv<R: Read>(&mut self, reader: R) -> Result<usize, String> {
+        if self.is_read_only() {
+            return Err("Cannot import into a read-only store".to_string());
+        }
+        let mut reader = csv::Reader::from_reader(reader);
+        let header = reader.headers().map_err(|e| e.to_string())?.clone();
+        let fields = &self.config.schema.fields;
+        // Position in the CSV record of each schema column
+        let mut positions = Vec::with_capacity(fields.len());
+        for field in fields {
+            let mut found = header.iter().enumerate().filter(|(_, h)| *h == field.name);
+            match (found.next(), found.next()) {
+                (Some((pos, _)), None) => positions.push(pos),
+                (None, _) => return Err(format!("CSV is missing column `{}`", field.name)),
+                (Some(_), Some(_)) => {
+                    return Err(format!("CSV repeats column `{}`", field.name));
+                }
+            }
+        }
+        if let Some(extra) = header.iter().find(|h| !fields.iter().any(|f| f.name == *h)) {
+            return Err(format!("CSV column `{}` is not in the schema", extra));
+        }
+
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(|e| e.to_string())?;
+            let line = record.position().map_or(0, |p| p.line());
+            let mut row = Vec::with_capacity(fields.len());
+            for (field, &pos) in fields.iter().zip(&positions) {
+                let cell = record[pos].trim();
+                let value = match field.data_type {
+                    DataType::Int64 => cell.parse().map(ScalarValue::Int64).ok(),
+                    DataType::Float64 => cell.parse().map(ScalarValue::Float64).ok(),
+                    _ => panic!("Unsupported data type in storage: {:?}", field.data_type),
+                };
+                row.push(value.ok_or_else(|| {
+                    format!(
+                        "line {}: `{}` is not a valid {:?} for column `{}`",
+                        line, cell, field.data_type, field.name
+                    )
+                })?);
+            }
+            rows.push(row);
+        }
+        let count = rows.len();
+        for row in rows {
+            self.put(row);
+        }
+        Ok(count)
+    }
+
+    /// Write every row as CSV, preceded by a header of the column names.
+    pub fn export_csv<W: Write>(&self, writer: W) -> Result<(), String> {
+        let mut writer = csv::Writer::from_writer(writer);
+        let fields = &self.config.schema.fields;
+        writer
+            .write_record(fields.iter().map(|f| &f.name))
+            .map_err(|e| e.to_string())?;
+        for index in 0..self.memtable_row_count() {
+            let row = self.get(index).unwrap();
+            writer
+                .write_record(row.iter().map(|value| match value {
+                    ScalarValue::Int64(v) => v.to_string(),
+                    ScalarValue::Float64(v) => v.to_string(),
+                }))
+                .map_err(|e| e.to_string())?;
+        }
+        writer.flush().map_err(|e| e.to_string())
+    }
+}
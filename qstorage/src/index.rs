@@ -0,0 +1,218 @@
+//! Secondary indexes: sorted maps from a column's values to row ids.
+//!
+//! Indexes live in memory only. They are built by [`QStore::create_index`]
+//! from the loaded column, kept up to date by `put`, and used by
+//! [`QStore::find`] in place of a full column scan.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeBounds};
+
+use arrow2::datatypes::{DataType, Field};
+
+use crate::{QStore, ScalarValue};
+
+/// A scalar ordered for indexing. Floats order by `total_cmp` once
+/// [`IndexKey::new`] has folded -0.0 into 0.0 and every NaN into one NaN,
+/// which sorts above infinity.
+#[derive(Debug, Clone)]
+pub(crate) struct IndexKey(pub(crate) ScalarValue);
+
+impl IndexKey {
+    pub(crate) fn new(value: &ScalarValue) -> IndexKey {
+        IndexKey(match *value {
+            ScalarValue::Float64(v) if v.is_nan() => ScalarValue::Float64(f64::NAN),
+            // Adding 0.0 turns -0.0 into 0.0 and leaves every other value
+            ScalarValue::Float64(v) => ScalarValue::Float64(v + 0.0),
+            ScalarValue::Int64(v) => ScalarValue::Int64(v),
+        })
+    }
+}
+
+impl Ord for IndexKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (&self.0, &other.0) {
+            (ScalarValue::Int64(a), ScalarValue::Int64(b)) => a.cmp(b),
+            (ScalarValue::Float64(a), ScalarValue::Float64(b)) => a.total_cmp(b),
+            // A column holds one type; callers check bounds against it
+            (ScalarValue::Int64(_), ScalarValue::Float64(_)) => Ordering::Less,
+            (ScalarValue::Float64(_), ScalarValue::Int64(_)) => Ordering::Greater,
+        }
+    }
+}
+
+impl PartialOrd for IndexKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for IndexKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for IndexKey {}
+
+/// Value -> ascending row ids.
+pub(crate) type Index = BTreeMap<IndexKey, Vec<usize>>;
+
+fn build(column: &[ScalarValue]) -> Index {
+    let mut index = Index::new();
+    for (row, value) in column.iter().enumerate() {
+        insert(&mut index, row, value);
+    }
+    index
+}
+
+pub(crate) fn insert(index: &mut Index, row: usize, value: &ScalarValue) {
+    index.entry(IndexKey::new(value)).or_default().push(row);
+}
+
+fn type_name(value: &ScalarValue) -> &'static str {
+    match value {
+        ScalarValue::Int64(_) => "Int64",
+        ScalarValue::Float64(_) => "Float64",
+    }
+}
+
+/// Fail unless `value` has the type of the column `field`.
+pub(crate) fn check_type(field: &Field, value: &ScalarValue) -> Result<(), String> {
+    match (&field.data_type, value) {
+        (DataType::Int64, ScalarValue::Int64(_)) | (DataType::Float64, ScalarValue::Float64(_)) => {
+            Ok(())
+        }
+        (data_type, _) => Err(format!(
+            "Column `{}` holds {:?} values, not {}",
+            field.name,
+            data_type,
+            type_name(value)
+        )),
+    }
+}
+
+/// Whether `bounds` contains no key, in which case `BTreeMap::range`
+/// would panic.
+fn is_empty(bounds: &(Bound<IndexKey>, Bound<IndexKey>)) -> bool {
+    match bounds {
+        (Bound::Included(s), Bound::Included(e)) => s > e,
+        (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) => {
+            s >= e
+        }
+        _ => false,
+    }
+}
+
+fn key_bound(bound: Bound<&ScalarValue>) -> Bound<IndexKey> {
+    bound.map(IndexKey::new)
+}
+
+fn is_nan(bound: &Bound<IndexKey>) -> bool {
+    matches!(bound, Bound::Included(IndexKey(ScalarValue::Float64(v))) | Bound::Excluded(IndexKey(ScalarValue::Float64(v))) if v.is_nan())
+}
+
+impl QStore {
+    /// The loaded values of the column `name`.
+    pub(crate) fn column(&self, name: &str) -> Result<&Vec<ScalarValue>, String> {
+        self.columns
+            .get(name)
+            .ok_or_else(|| format!("No column `{}`", name))
+    }
+
+    /// The schema field of the column `name`.
+    pub(crate) fn field(&self, name: &str) -> Result<&Field, String> {
+        self.config
+            .schema
+            .fields
+            .iter()
+            .find(|f| f.name == name)
+            .ok_or_else(|| format!("No column `{}`", name))
+    }
+
+    /// Build an index on `column`, replacing any existing one.
+    pub fn create_index(&mut self, column: &str) -> Result<(), String> {
+        let index = build(self.column(column)?);
+        self.indexes.insert(column.to_string(), index);
+        Ok(())
+    }
+
+    /// Drop the index on `column`, returning whether there was one.
+    pub fn drop_index(&mut self, column: &str) -> bool {
+        self.indexes.remove(column).is_some()
+    }
+
+    /// Rebuild the index on `column` from the column's values.
+    pub fn rebuild_index(&mut self, column: &str) -> Result<(), String> {
+        if !self.indexes.contains_key(column) {
+            return Err(format!("No index on `{}`", column));
+        }
+        self.create_index(column)
+    }
+
+    /// Names of the indexed columns, sorted.
+    pub fn indexed_columns(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.indexes.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    /// Verify that every index matches its column, naming the first one
+    /// that does not.
+    pub fn check_indexes(&self) -> Result<(), String> {
+        for name in self.indexed_columns() {
+            if self.indexes[name] != build(self.column(name)?) {
+                return Err(format!(
+                    "Index on `{}` is inconsistent with its column",
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Ids, ascending, of the rows whose `column` value lies in `range`
+    /// (use `v.clone()..=v` for equality). -0.0 and 0.0 are one value, as
+    /// are all NaNs, and only a range with a NaN bound matches NaN. Uses
+    /// the column's index if it has one and scans the column otherwise;
+    /// both give the same rows.
+    pub fn find<R: RangeBounds<ScalarValue>>(
+        &self,
+        column: &str,
+        range: R,
+    ) -> Result<Vec<usize>, String> {
+        let values = self.column(column)?;
+        let field = self.field(column)?;
+        for bound in [range.start_bound(), range.end_bound()] {
+            if let Bound::Included(v) | Bound::Excluded(v) = bound {
+                check_type(field, v)?;
+            }
+        }
+        let mut bounds = (key_bound(range.start_bound()), key_bound(range.end_bound()));
+        // NaN sorts above infinity, so stop open float ranges short of it
+        if field.data_type == DataType::Float64
+            && bounds.1 == Bound::Unbounded
+            && !is_nan(&bounds.0)
+        {
+            bounds.1 = Bound::Included(IndexKey(ScalarValue::Float64(f64::INFINITY)));
+        }
+        if is_empty(&bounds) {
+            return Ok(Vec::new());
+        }
+        let mut rows: Vec<usize> = match self.indexes.get(column) {
+            Some(index) => index
+                .range(bounds)
+                .flat_map(|(_, rows)| rows)
+                .copied()
+                .collect(),
+            None => values
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| bounds.contains(&IndexKey::new(v)))
+                .map(|(row, _)| row)
+                .collect(),
+        };
+        rows.sort_unstable();
+        Ok(rows)
+    }
+}
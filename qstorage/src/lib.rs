@@ -1,10 +1,20 @@
 use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
+use std::fs::{File, OpenOptions, TryLockError};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
 use arrow2::datatypes::{Field, Schema};
 
+pub mod batch;
+pub mod codec;
+mod csv_io;
+mod index;
+pub mod query;
+pub mod retention;
+pub mod rollup;
+pub mod tags;
+pub mod txn;
+
 // Column-family data model
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
@@ -53,6 +63,22 @@ pub enum ScalarValue {
     // Extend with more types as needed
 }
 
+/// On-disk encoding of a value: 8 little-endian bytes.
+fn value_bytes(value: &ScalarValue) -> [u8; 8] {
+    match value {
+        ScalarValue::Int64(v) => v.to_le_bytes(),
+        ScalarValue::Float64(v) => v.to_le_bytes(),
+    }
+}
+
+/// A value as a float, for aggregates over either column type.
+fn as_f64(value: &ScalarValue) -> f64 {
+    match value {
+        ScalarValue::Int64(v) => *v as f64,
+        ScalarValue::Float64(v) => *v,
+    }
+}
+
 /// Configuration for the splayed table storage.
 pub struct QStoreConfig {
     pub schema: Schema,
@@ -60,17 +86,121 @@ pub struct QStoreConfig {
     pub data_dir: PathBuf,
 }
 
+/// Name of the lock file a writer holds in the data directory.
+pub const LOCK_FILE: &str = "LOCK";
+
+/// Name of the file recording the store's on-disk format version.
+pub const FORMAT_FILE: &str = "FORMAT";
+
+/// On-disk format written by this build: one headerless file of
+/// little-endian 8-byte values per column. Stores created before the
+/// format file existed have this layout too.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Check that this build can read the store in `data_dir`. Returns whether
+/// the store records its format version.
+fn check_format(data_dir: &Path) -> Result<bool, String> {
+    let path = data_dir.join(FORMAT_FILE);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.to_string()),
+    };
+    let version: u32 = text
+        .trim()
+        .parse()
+        .map_err(|_| format!("Unreadable format version in {}", path.display()))?;
+    if version > FORMAT_VERSION {
+        return Err(format!(
+            "Store {} has format version {}; this build reads versions up to {}",
+            data_dir.display(),
+            version,
+            FORMAT_VERSION
+        ));
+    }
+    Ok(true)
+}
+
 /// Splayed table storage: one file per column, memory-backed arrays on init.
 pub struct QStore {
     config: QStoreConfig,
     columns: HashMap<String, Vec<ScalarValue>>,
+    /// Exclusive lock on `LOCK_FILE`, held while a writer is open;
+    /// `None` for a read-only store.
+    lock: Option<File>,
+    /// Secondary indexes by column name (see [`QStore::create_index`])
+    indexes: HashMap<String, index::Index>,
+}
+
+/// Read a column file as little-endian values of `data_type`. A missing
+/// file is an empty column.
+fn load_column(path: &Path, data_type: &arrow2::datatypes::DataType) -> Vec<ScalarValue> {
+    let mut vec = Vec::new();
+    if path.exists() {
+        let mut file = File::open(path).unwrap();
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf).unwrap();
+        // Interpret bytes as little-endian values based on data_type
+        match data_type {
+            arrow2::datatypes::DataType::Int64 => {
+                for chunk in buf.chunks_exact(8) {
+                    let v = i64::from_le_bytes(chunk.try_into().unwrap());
+                    vec.push(ScalarValue::Int64(v));
+                }
+            }
+            arrow2::datatypes::DataType::Float64 => {
+                for chunk in buf.chunks_exact(8) {
+                    let v = f64::from_le_bytes(chunk.try_into().unwrap());
+                    vec.push(ScalarValue::Float64(v));
+                }
+            }
+            _ => {
+                // Unsupported type
+                panic!("Unsupported data type in storage: {:?}", data_type);
+            }
+        }
+    }
+    vec
 }
 
 impl QStore {
     /// Initialize the storage: load existing column files and prepare in-memory arrays.
+    /// Panics if the store cannot be opened; see [`QStore::open`].
     pub fn init(config: QStoreConfig) -> Self {
+        QStore::open(config).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Open the store for writing, creating the data directory and column
+    /// files as needed, and dropping any rows a crash left half-written
+    /// (see [`txn`]). Fails if another writer (in this or any other
+    /// process) has the store open.
+    pub fn open(config: QStoreConfig) -> Result<Self, String> {
         // Ensure data directory exists
-        std::fs::create_dir_all(&config.data_dir).unwrap();
+        std::fs::create_dir_all(&config.data_dir).map_err(|e| e.to_string())?;
+        let lock_path = config.data_dir.join(LOCK_FILE);
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|e| e.to_string())?;
+        match lock.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) => {
+                return Err(format!(
+                    "Store {} is locked by another writer",
+                    config.data_dir.display()
+                ));
+            }
+            Err(TryLockError::Error(e)) => return Err(e.to_string()),
+        }
+        if !check_format(&config.data_dir)? {
+            std::fs::write(
+                config.data_dir.join(FORMAT_FILE),
+                format!("{}\n", FORMAT_VERSION),
+            )
+            .map_err(|e| e.to_string())?;
+        }
         let mut columns = HashMap::new();
         // For each column in schema, load existing data
         for Field {
@@ -78,57 +208,86 @@ impl QStore {
         } in &config.schema.fields
         {
             let path = config.data_dir.join(name);
-            let mut vec = Vec::new();
-            if Path::new(&path).exists() {
-                let mut file = File::open(&path).unwrap();
-                let mut buf = Vec::new();
-                file.read_to_end(&mut buf).unwrap();
-                // Interpret bytes as little-endian values based on data_type
-                match data_type {
-                    arrow2::datatypes::DataType::Int64 => {
-                        for chunk in buf.chunks_exact(8) {
-                            let v = i64::from_le_bytes(chunk.try_into().unwrap());
-                            vec.push(ScalarValue::Int64(v));
-                        }
-                    }
-                    arrow2::datatypes::DataType::Float64 => {
-                        for chunk in buf.chunks_exact(8) {
-                            let v = f64::from_le_bytes(chunk.try_into().unwrap());
-                            vec.push(ScalarValue::Float64(v));
-                        }
-                    }
-                    _ => {
-                        // Unsupported type
-                        panic!("Unsupported data type in storage: {:?}", data_type);
-                    }
-                }
-            }
+            columns.insert(name.clone(), load_column(&path, data_type));
             // Ensure the file exists for future writes
             OpenOptions::new()
                 .create(true)
                 .append(true)
                 .open(&path)
-                .unwrap();
-            columns.insert(name.clone(), vec);
+                .map_err(|e| e.to_string())?;
+        }
+        let mut store = QStore {
+            config,
+            columns,
+            lock: Some(lock),
+            indexes: HashMap::new(),
+        };
+        store.recover()?;
+        Ok(store)
+    }
+
+    /// Open the store without writing anything to disk and without taking
+    /// the writer lock, so it can be read while another process writes.
+    /// Columns are cut to the shortest one, and to the start of any batch
+    /// still being committed, so only whole rows and batches are seen. A
+    /// column an interrupted expiry has not yet swapped in is read from
+    /// its rewritten file.
+    pub fn open_read_only(config: QStoreConfig) -> Result<Self, String> {
+        if !config.data_dir.is_dir() {
+            return Err(format!("No store at {}", config.data_dir.display()));
         }
-        QStore { config, columns }
+        check_format(&config.data_dir)?;
+        let swapping = config.data_dir.join(retention::EXPIRING_FILE).exists();
+        let mut columns: HashMap<String, Vec<ScalarValue>> = config
+            .schema
+            .fields
+            .iter()
+            .map(|field| {
+                let mut path = retention::expiring_path(&config.data_dir, &field.name);
+                if !swapping || !path.exists() {
+                    path = config.data_dir.join(&field.name);
+                }
+                (field.name.clone(), load_column(&path, &field.data_type))
+            })
+            .collect();
+        let mut rows = columns.values().map(Vec::len).min().unwrap_or(0);
+        if let Some(pending) = txn::pending_rows(&config.data_dir)? {
+            rows = rows.min(pending);
+        }
+        for column in columns.values_mut() {
+            column.truncate(rows);
+        }
+        Ok(QStore {
+            config,
+            columns,
+            lock: None,
+            indexes: HashMap::new(),
+        })
+    }
+
+    /// Whether the store was opened with [`QStore::open_read_only`].
+    pub fn is_read_only(&self) -> bool {
+        self.lock.is_none()
     }
 
     /// Append a new row of values, writing each value to its column file.
+    /// Panics if the store is read-only.
     pub fn put(&mut self, row: Vec<ScalarValue>) {
+        assert!(!self.is_read_only(), "Cannot put into a read-only store");
+        let row_id = self.memtable_row_count();
         for (i, value) in row.into_iter().enumerate() {
             let field = &self.config.schema.fields[i];
             let name = &field.name;
             let col = self.columns.get_mut(name).unwrap();
             // Append in-memory
             col.push(value.clone());
+            if let Some(index) = self.indexes.get_mut(name) {
+                index::insert(index, row_id, &value);
+            }
             // Append to disk
             let path = self.config.data_dir.join(name);
             let mut file = OpenOptions::new().append(true).open(&path).unwrap();
-            match value {
-                ScalarValue::Int64(v) => file.write_all(&v.to_le_bytes()).unwrap(),
-                ScalarValue::Float64(v) => file.write_all(&v.to_le_bytes()).unwrap(),
-            }
+            file.write_all(&value_bytes(&value)).unwrap();
         }
     }
 
@@ -157,6 +316,20 @@ mod tests {
     use arrow2::datatypes::{DataType, Field};
     use tempfile::tempdir;
 
+    /// A store in `dir` whose non-nullable columns are `fields`.
+    fn store_config(dir: &Path, fields: &[(&str, DataType)]) -> QStoreConfig {
+        QStoreConfig {
+            schema: Schema {
+                fields: fields
+                    .iter()
+                    .map(|(name, data_type)| Field::new(*name, data_type.clone(), false))
+                    .collect(),
+                metadata: Default::default(),
+            },
+            data_dir: dir.to_path_buf(),
+        }
+    }
+
     #[test]
     fn test_put_and_count() {
         let dir = tempdir().unwrap();
@@ -178,4 +351,693 @@ mod tests {
         let data = std::fs::read(&file_path).unwrap();
         assert_eq!(i64::from_le_bytes(data[..8].try_into().unwrap()), 42);
     }
+
+    #[test]
+    fn test_single_writer_and_read_only_open() {
+        let dir = tempdir().unwrap();
+        let config = || store_config(dir.path(), &[("col", DataType::Int64)]);
+        let missing = QStoreConfig {
+            data_dir: dir.path().join("missing"),
+            ..config()
+        };
+        assert!(QStore::open_read_only(missing).is_err());
+
+        let mut writer = QStore::open(config()).unwrap();
+        writer.put(vec![ScalarValue::Int64(1)]);
+        let err = QStore::open(config()).err().unwrap();
+        assert!(err.ends_with("is locked by another writer"), "{err}");
+
+        let reader = QStore::open_read_only(config()).unwrap();
+        assert!(reader.is_read_only());
+        assert_eq!(reader.get(0), Some(vec![ScalarValue::Int64(1)]));
+
+        drop(writer);
+        assert!(QStore::open(config()).is_ok());
+    }
+
+    #[test]
+    fn test_format_version_is_recorded_and_checked() {
+        let dir = tempdir().unwrap();
+        let config = || store_config(dir.path(), &[("col", DataType::Int64)]);
+        drop(QStore::open(config()).unwrap());
+        let format = std::fs::read_to_string(dir.path().join(FORMAT_FILE)).unwrap();
+        assert_eq!(format, format!("{}\n", FORMAT_VERSION));
+
+        std::fs::write(dir.path().join(FORMAT_FILE), "99\n").unwrap();
+        let expected = format!(
+            "Store {} has format version 99; this build reads versions up to {}",
+            dir.path().display(),
+            FORMAT_VERSION
+        );
+        assert_eq!(QStore::open(config()).err(), Some(expected.clone()));
+        assert_eq!(QStore::open_read_only(config()).err(), Some(expected));
+    }
+
+    #[test]
+    fn test_find_with_and_without_index() {
+        let dir = tempdir().unwrap();
+        let config = store_config(
+            dir.path(),
+            &[("id", DataType::Int64), ("price", DataType::Float64)],
+        );
+        let mut store = QStore::init(config);
+        let prices = [3.0, 1.5, f64::NAN, 3.0];
+        for (id, price) in prices.iter().enumerate() {
+            store.put(vec![
+                ScalarValue::Int64(id as i64),
+                ScalarValue::Float64(*price),
+            ]);
+        }
+        let queries = |store: &QStore| {
+            let three = ScalarValue::Float64(3.0);
+            [
+                store.find("price", three.clone()..=three.clone()).unwrap(),
+                store.find("price", ..three.clone()).unwrap(),
+                store
+                    .find("price", ScalarValue::Float64(f64::NAN)..)
+                    .unwrap(),
+                store.find("price", three.clone()..three).unwrap(),
+            ]
+        };
+        let scanned = queries(&store);
+        assert_eq!(scanned, [vec![0, 3], vec![1], vec![2], vec![]]);
+
+        store.create_index("price").unwrap();
+        assert_eq!(queries(&store), scanned);
+        // Rows put after the index is built are indexed too
+        store.put(vec![ScalarValue::Int64(4), ScalarValue::Float64(3.0)]);
+        assert_eq!(queries(&store)[0], [0, 3, 4]);
+        assert_eq!(store.indexed_columns(), ["price"]);
+        assert_eq!(store.check_indexes(), Ok(()));
+
+        // -0.0 is 0.0, -NaN is NaN, and open ranges stop short of NaN
+        for (id, price) in [(5, -0.0), (6, -f64::NAN), (7, 0.0)] {
+            store.put(vec![ScalarValue::Int64(id), ScalarValue::Float64(price)]);
+        }
+        let zero = ScalarValue::Float64(0.0);
+        let signed = |store: &QStore| {
+            [
+                store.find("price", zero.clone()..=zero.clone()).unwrap(),
+                store.find("price", ScalarValue::Float64(-0.0)..).unwrap(),
+                store.find("price", ..zero.clone()).unwrap(),
+                store
+                    .find("price", ScalarValue::Float64(-f64::NAN)..)
+                    .unwrap(),
+            ]
+        };
+        let indexed = signed(&store);
+        assert_eq!(
+            indexed,
+            [vec![5, 7], vec![0, 1, 3, 4, 5, 7], vec![], vec![2, 6]]
+        );
+        store.drop_index("price");
+        assert_eq!(signed(&store), indexed);
+        store.create_index("price").unwrap();
+
+        assert_eq!(
+            store.find("id", ScalarValue::Float64(1.0)..),
+            Err("Column `id` holds Int64 values, not Float64".to_string())
+        );
+        assert!(store.create_index("nope").is_err());
+        assert!(store.drop_index("price"));
+        assert!(store.rebuild_index("price").is_err());
+    }
+
+    #[test]
+    fn test_tag_index_query() {
+        use crate::tags::TagIndex;
+
+        let tags = |list: &[&str]| -> TagSet { list.iter().map(|t| t.to_string()).collect() };
+        let mut index = TagIndex::new();
+        index.insert(3, &tags(&["host=a", "region=eu"]));
+        index.insert(1, &tags(&["host=b", "region=eu"]));
+        index.insert(2, &tags(&["region=eu", "host=a", "host=a"]));
+        assert_eq!(index.query(&["region=eu"]), [1, 2, 3]);
+        assert_eq!(index.query(&["host=a", "region=eu"]), [2, 3]);
+        assert_eq!(index.query(&["host=c"]), Vec::<u64>::new());
+        assert_eq!(index.query(&[]), [1, 2, 3]);
+        assert_eq!(index.tags(2), Some(tags(&["host=a", "region=eu"])));
+
+        // Re-inserting replaces a series' tags
+        index.insert(3, &tags(&["host=b"]));
+        assert_eq!(index.query(&["host=a"]), [2]);
+        assert_eq!(index.query(&["host=b"]), [1, 3]);
+        assert!(index.remove(1));
+        assert_eq!(index.query(&["host=b"]), [3]);
+        assert_eq!(index.tags(1), None);
+    }
+
+    #[test]
+    fn test_expire_before_drops_old_rows() {
+        use crate::retention::RetentionReport;
+
+        let dir = tempdir().unwrap();
+        let config = || {
+            store_config(
+                dir.path(),
+                &[("ts", DataType::Int64), ("v", DataType::Float64)],
+            )
+        };
+        let mut store = QStore::init(config());
+        for ts in [10, 20, 30, 15, 40] {
+            store.put(vec![
+                ScalarValue::Int64(ts),
+                ScalarValue::Float64(ts as f64),
+            ]);
+        }
+        store.create_index("v").unwrap();
+        let report = store.expire_before("ts", 25, true).unwrap();
+        assert_eq!(
+            report,
+            RetentionReport {
+                rows: 3,
+                bytes: 48,
+                dry_run: true
+            }
+        );
+        assert_eq!(store.memtable_row_count(), 5);
+
+        // The late row (15) goes too, though it follows a newer one
+        assert_eq!(store.expire_before("ts", 25, false).unwrap().rows, 3);
+        assert_eq!(
+            store.get(1),
+            Some(vec![ScalarValue::Int64(40), ScalarValue::Float64(40.0)])
+        );
+        assert_eq!(store.find("v", ScalarValue::Float64(40.0)..), Ok(vec![1]));
+        assert_eq!(store.check_indexes(), Ok(()));
+        assert!(store.expire_before("v", 0, true).is_err());
+        drop(store);
+
+        let reopened = QStore::open_read_only(config()).unwrap();
+        assert_eq!(reopened.memtable_row_count(), 2);
+        assert_eq!(std::fs::metadata(dir.path().join("ts")).unwrap().len(), 16);
+
+        // A crash before the marker: the rewritten files are discarded
+        let expiring = |name| retention::expiring_path(dir.path(), name);
+        std::fs::write(expiring("ts"), 15i64.to_le_bytes()).unwrap();
+        let store = QStore::init(config());
+        assert_eq!(store.memtable_row_count(), 2);
+        assert!(!expiring("ts").exists());
+        drop(store);
+
+        // A crash mid-swap: `ts` is swapped in, `v` is not yet
+        std::fs::write(dir.path().join("ts"), 15i64.to_le_bytes()).unwrap();
+        std::fs::write(expiring("v"), 15f64.to_le_bytes()).unwrap();
+        std::fs::write(dir.path().join(retention::EXPIRING_FILE), "1\n").unwrap();
+        let kept = vec![ScalarValue::Int64(15), ScalarValue::Float64(15.0)];
+        let reader = QStore::open_read_only(config()).unwrap();
+        assert_eq!(reader.memtable_row_count(), 1);
+        assert_eq!(reader.get(0), Some(kept.clone()));
+        let store = QStore::init(config());
+        assert_eq!(store.memtable_row_count(), 1);
+        assert_eq!(store.get(0), Some(kept));
+        assert!(!expiring("v").exists());
+        assert!(!dir.path().join(retention::EXPIRING_FILE).exists());
+    }
+
+    #[test]
+    fn test_rollup_into_is_incremental() {
+        use crate::rollup::{Rollup, rollup_schema};
+
+        let raw_dir = tempdir().unwrap();
+        let mut raw = QStore::init(store_config(
+            raw_dir.path(),
+            &[("ts", DataType::Int64), ("v", DataType::Float64)],
+        ));
+        for (ts, v) in [(0, 1.0), (30, 3.0), (59, 2.0), (60, 10.0), (125, 7.0)] {
+            raw.put(vec![ScalarValue::Int64(ts), ScalarValue::Float64(v)]);
+        }
+        assert_eq!(
+            raw.rollup("ts", "v", 60, i64::MIN).unwrap()[0],
+            Rollup {
+                bucket: 0,
+                count: 3,
+                min: 1.0,
+                max: 3.0,
+                avg: 2.0
+            }
+        );
+
+        let rollup_dir = tempdir().unwrap();
+        let mut minutes = QStore::init(QStoreConfig {
+            schema: rollup_schema(),
+            data_dir: rollup_dir.path().to_path_buf(),
+        });
+        // Only the buckets ending by t=120 are complete
+        assert_eq!(raw.rollup_into(&mut minutes, "ts", "v", 60, 130), Ok(2));
+        assert_eq!(raw.rollup_into(&mut minutes, "ts", "v", 60, 130), Ok(0));
+        assert_eq!(raw.rollup_into(&mut minutes, "ts", "v", 60, 180), Ok(1));
+        assert_eq!(minutes.get(2).unwrap()[0], ScalarValue::Int64(120));
+        assert!(raw.rollup_into(&mut minutes, "ts", "v", 0, 180).is_err());
+
+        // Buckets at the end of the time range never overflow
+        raw.put(vec![
+            ScalarValue::Int64(i64::MAX),
+            ScalarValue::Float64(1.0),
+        ]);
+        assert_eq!(
+            raw.rollup_into(&mut minutes, "ts", "v", 60, i64::MAX),
+            Ok(0)
+        );
+        let last = i64::MAX - i64::MAX % 60;
+        assert_eq!(raw.rollup_into(&mut minutes, "ts", "v", 60, last), Ok(0));
+        assert_eq!(raw.rollup("ts", "v", 60, 180).unwrap()[0].bucket, last);
+
+        drop(minutes);
+        let mut reader = QStore::open_read_only(QStoreConfig {
+            schema: rollup_schema(),
+            data_dir: rollup_dir.path().to_path_buf(),
+        })
+        .unwrap();
+        assert_eq!(
+            raw.rollup_into(&mut reader, "ts", "v", 60, 180),
+            Err("Cannot write to a read-only store".to_string())
+        );
+
+        // Nor at the start, where a bucket may begin before `i64::MIN`
+        let early_dir = tempdir().unwrap();
+        let mut early = QStore::init(store_config(
+            early_dir.path(),
+            &[("ts", DataType::Int64), ("v", DataType::Float64)],
+        ));
+        for ts in [i64::MIN, i64::MIN + 1] {
+            early.put(vec![ScalarValue::Int64(ts), ScalarValue::Float64(1.0)]);
+        }
+        assert_eq!(
+            early.rollup("ts", "v", 60, i64::MIN),
+            Err(format!(
+                "Time {} is in a 60-wide bucket that starts before the earliest Int64 time",
+                i64::MIN
+            ))
+        );
+        let buckets = early.rollup("ts", "v", 4, i64::MIN).unwrap();
+        assert_eq!((buckets[0].bucket, buckets[0].count), (i64::MIN, 2));
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let dir = tempdir().unwrap();
+        let config = store_config(
+            dir.path(),
+            &[("ts", DataType::Int64), ("price", DataType::Float64)],
+        );
+        let mut store = QStore::init(config);
+        // Columns may appear in any order
+        let added = store.import_csv("price,ts\n1.5,10\n-2,11\n".as_bytes());
+        assert_eq!(added, Ok(2));
+        assert_eq!(
+            store.get(1),
+            Some(vec![ScalarValue::Int64(11), ScalarValue::Float64(-2.0)])
+        );
+
+        // A bad cell rejects the whole import
+        assert_eq!(
+            store.import_csv("ts,price\n12,3\n1.5,4\n".as_bytes()),
+            Err("line 3: `1.5` is not a valid Int64 for column `ts`".to_string())
+        );
+        assert_eq!(
+            store.import_csv("ts\n12\n".as_bytes()),
+            Err("CSV is missing column `price`".to_string())
+        );
+        assert_eq!(store.memtable_row_count(), 2);
+
+        let mut out = Vec::new();
+        store.export_csv(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "ts,price\n10,1.5\n11,-2\n");
+    }
+
+    #[test]
+    fn test_codecs_round_trip_and_pick_the_smallest() {
+        use codec::*;
+
+        let ints = [0, 1, -1, i64::MIN, i64::MAX, 7, 7, 7, 1_700_000_000];
+        assert_eq!(
+            decode_delta_of_delta(&encode_delta_of_delta(&ints)),
+            Ok(ints.to_vec())
+        );
+        assert_eq!(
+            decode_run_length(&encode_run_length(&ints)),
+            Ok(ints.to_vec())
+        );
+        let floats = [
+            1.5,
+            1.5,
+            1.25,
+            -0.0,
+            f64::INFINITY,
+            f64::MIN_POSITIVE,
+            3.0e300,
+            0.1,
+        ];
+        let decoded = decode_gorilla(&encode_gorilla(&floats)).unwrap();
+        assert_eq!(decoded, floats.to_vec());
+        assert!(decode_gorilla(&encode_gorilla(&[f64::NAN])).unwrap()[0].is_nan());
+        assert_eq!(decode_gorilla(&encode_gorilla(&[])), Ok(vec![]));
+        assert!(decode_delta_of_delta(&[3, 2]).is_err());
+        assert!(decode_run_length(&[3, 2, 5]).is_err());
+        // Corrupt input is an error, never a panic or a wrong column
+        let mut trailing = encode_delta_of_delta(&ints);
+        trailing.push(0);
+        assert_eq!(
+            decode_delta_of_delta(&trailing),
+            Err("Trailing bytes after delta-of-delta data".to_string())
+        );
+        // A run of usize::MAX values after one value
+        let mut huge = vec![2, 0, 1, 0];
+        huge.extend([0xff; 9]);
+        huge.push(0x01);
+        assert_eq!(
+            decode_run_length(&huge),
+            Err("Run-length data exceeds its count".to_string())
+        );
+
+        let dir = tempdir().unwrap();
+        let mut store = QStore::init(store_config(
+            dir.path(),
+            &[
+                ("ts", DataType::Int64),
+                ("status", DataType::Int64),
+                ("temp", DataType::Float64),
+            ],
+        ));
+        for i in 0..1000 {
+            store.put(vec![
+                ScalarValue::Int64(1_700_000_000 + 10 * i),
+                ScalarValue::Int64(i / 500),
+                ScalarValue::Float64(20.0 + (i / 100) as f64 * 0.5),
+            ]);
+        }
+        let stats = store.compression_stats();
+        let codecs: Vec<(&str, Codec)> =
+            stats.iter().map(|s| (s.column.as_str(), s.codec)).collect();
+        assert_eq!(
+            codecs,
+            [
+                ("ts", Codec::DeltaOfDelta),
+                ("status", Codec::RunLength),
+                ("temp", Codec::Gorilla)
+            ]
+        );
+        // Regular timestamps take about a byte each, constant runs almost nothing
+        assert!(stats[0].ratio() > 7.0, "{:?}", stats[0]);
+        assert!(stats[1].encoded_bytes < 10, "{:?}", stats[1]);
+        assert!(stats[2].ratio() > 30.0, "{:?}", stats[2]);
+    }
+
+    #[test]
+    fn test_query_aggregates_with_where_and_group_by() {
+        use query::QueryResult;
+
+        let dir = tempdir().unwrap();
+        let mut store = QStore::init(store_config(
+            dir.path(),
+            &[
+                ("ts", DataType::Int64),
+                ("host", DataType::Int64),
+                ("v", DataType::Float64),
+            ],
+        ));
+        for (ts, host, v) in [(10, 1, 1.0), (20, 2, 2.0), (30, 1, 3.0), (40, 2, -4.0)] {
+            store.put(vec![
+                ScalarValue::Int64(ts),
+                ScalarValue::Int64(host),
+                ScalarValue::Float64(v),
+            ]);
+        }
+        store.create_index("ts").unwrap();
+
+        assert_eq!(
+            store.query(
+                "SELECT count(*), sum(ts), avg(v) FROM m WHERE ts BETWEEN 15 AND 40 AND v > 0"
+            ),
+            Ok(QueryResult {
+                columns: vec!["count(*)".into(), "sum(ts)".into(), "avg(v)".into()],
+                rows: vec![vec![
+                    ScalarValue::Int64(2),
+                    ScalarValue::Int64(50),
+                    ScalarValue::Float64(2.5)
+                ]],
+            })
+        );
+        assert_eq!(
+            store
+                .query("select host, min(v), max(ts) group by host")
+                .unwrap()
+                .rows,
+            vec![
+                vec![
+                    ScalarValue::Int64(1),
+                    ScalarValue::Float64(1.0),
+                    ScalarValue::Int64(30)
+                ],
+                vec![
+                    ScalarValue::Int64(2),
+                    ScalarValue::Float64(-4.0),
+                    ScalarValue::Int64(40)
+                ],
+            ]
+        );
+        // Aggregates over no rows: count is 0, avg is NaN
+        let empty = store.query("select count(v), avg(v) where ts < 0").unwrap();
+        assert_eq!(empty.rows[0][0], ScalarValue::Int64(0));
+        assert!(matches!(empty.rows[0][1], ScalarValue::Float64(f) if f.is_nan()));
+
+        assert_eq!(
+            store.query("select v"),
+            Err("Column `v` must be aggregated or grouped by".to_string())
+        );
+        assert_eq!(
+            store.query("select avg(w)"),
+            Err("No column `w`".to_string())
+        );
+        assert_eq!(
+            store.query("select count(*) where ts = 1.5"),
+            Err("`1.5` is not a valid Int64 for column `ts`".to_string())
+        );
+        assert_eq!(
+            store.query("select count(*) where ts between 1"),
+            Err("Expected `and` at end of query".to_string())
+        );
+        assert_eq!(
+            store.query("select count(*) where host <> 1 and ts != 20"),
+            Ok(QueryResult {
+                columns: vec!["count(*)".into()],
+                rows: vec![vec![ScalarValue::Int64(1)]],
+            })
+        );
+        assert_eq!(
+            store.query("select count(*) where host ! 1"),
+            Err("Unexpected `!` in query".to_string())
+        );
+        assert_eq!(
+            store.query("select count(*) limit 1"),
+            Err("Unexpected `limit` in query".to_string())
+        );
+    }
+
+    #[test]
+    fn test_query_compares_floats_as_filter_does() {
+        use batch::{ColumnExpr, CompareOp};
+
+        let dir = tempdir().unwrap();
+        let mut store = QStore::init(store_config(
+            dir.path(),
+            &[("row", DataType::Int64), ("v", DataType::Float64)],
+        ));
+        for (row, v) in [-0.0, f64::NAN, 1.0, 0.0, -f64::NAN].iter().enumerate() {
+            store.put(vec![
+                ScalarValue::Int64(row as i64),
+                ScalarValue::Float64(*v),
+            ]);
+        }
+        // The rows a `where` clause keeps, read back through `group by`
+        let matched = |store: &QStore, clause: &str| -> Vec<usize> {
+            let query = format!("select row where {} group by row", clause);
+            let result = store.query(&query).unwrap();
+            result
+                .rows
+                .iter()
+                .map(|r| match r[0] {
+                    ScalarValue::Int64(row) => row as usize,
+                    _ => unreachable!(),
+                })
+                .collect()
+        };
+        let ops = [
+            ("<", CompareOp::Lt),
+            ("<=", CompareOp::Le),
+            (">", CompareOp::Gt),
+            (">=", CompareOp::Ge),
+            ("=", CompareOp::Eq),
+            ("<>", CompareOp::Ne),
+            ("!=", CompareOp::Ne),
+        ];
+        let check = |store: &QStore| {
+            for literal in ["0", "-0", "1", "NaN", "-NaN", "inf"] {
+                let value: f64 = literal.parse().unwrap();
+                for (symbol, op) in ops {
+                    let expr = ColumnExpr::Compare(
+                        op,
+                        Box::new(ColumnExpr::column("v")),
+                        Box::new(ColumnExpr::Literal(ScalarValue::Float64(value))),
+                    );
+                    let clause = format!("v {} {}", symbol, literal);
+                    assert_eq!(
+                        matched(store, &clause),
+                        store.filter(&expr).unwrap(),
+                        "{clause}"
+                    );
+                }
+            }
+            assert_eq!(matched(store, "v > 0"), [2]);
+            assert_eq!(matched(store, "v = 0"), [0, 3]);
+            assert_eq!(matched(store, "v < 0"), Vec::<usize>::new());
+            assert_eq!(matched(store, "v <> 0"), [1, 2, 4]);
+            assert_eq!(matched(store, "v != NaN"), [0, 1, 2, 3, 4]);
+            assert_eq!(matched(store, "v between -0 and 1"), [0, 2, 3]);
+            assert_eq!(matched(store, "v between -1 and NaN"), Vec::<usize>::new());
+        };
+        check(&store);
+        store.create_index("v").unwrap();
+        check(&store);
+    }
+
+    #[test]
+    fn test_batch_expressions_match_row_at_a_time() {
+        use batch::{ArithOp, ColumnExpr, CompareOp, Value};
+
+        let dir = tempdir().unwrap();
+        let mut store = QStore::init(store_config(
+            dir.path(),
+            &[("ts", DataType::Int64), ("v", DataType::Float64)],
+        ));
+        // More rows than one batch
+        for i in 0..10_000 {
+            store.put(vec![
+                ScalarValue::Int64(i),
+                ScalarValue::Float64((i % 7) as f64),
+            ]);
+        }
+        let col = |name| Box::new(ColumnExpr::column(name));
+        let int = |v| Box::new(ColumnExpr::Literal(ScalarValue::Int64(v)));
+        // (ts - 5000) * 3
+        let shifted = ColumnExpr::Arith(
+            ArithOp::Mul,
+            Box::new(ColumnExpr::Arith(ArithOp::Sub, col("ts"), int(5000))),
+            int(3),
+        );
+        // not (v < 3) or ts / 2 = 50
+        let predicate = ColumnExpr::Or(
+            Box::new(ColumnExpr::Not(Box::new(ColumnExpr::Compare(
+                CompareOp::Lt,
+                col("v"),
+                int(3),
+            )))),
+            Box::new(ColumnExpr::Compare(
+                CompareOp::Eq,
+                Box::new(ColumnExpr::Arith(ArithOp::Div, col("ts"), int(2))),
+                int(50),
+            )),
+        );
+
+        let rows = store.filter(&predicate).unwrap();
+        let expected: Vec<usize> = (0..10_000)
+            .filter(|&row| predicate.eval_row(&store, row) == Ok(Value::Bool(true)))
+            .collect();
+        assert_eq!(rows, expected);
+        // Rows with ts % 7 >= 3, plus ts = 100
+        assert_eq!(rows.len(), 5_714);
+        assert!(rows.contains(&100));
+
+        let computed = store.compute(&shifted).unwrap();
+        assert_eq!(computed.len(), 10_000);
+        assert_eq!(computed[9_999], ScalarValue::Int64(14_997));
+        assert_eq!(shifted.eval_row(&store, 0), Ok(Value::Int64(-15_000)));
+
+        assert_eq!(
+            store.filter(&shifted),
+            Err("Filter expression is not a predicate".to_string())
+        );
+        assert_eq!(
+            store.compute(&predicate),
+            Err("A computed column must be numeric".to_string())
+        );
+        assert_eq!(
+            store.filter(&ColumnExpr::Not(col("w"))),
+            Err("No column `w`".to_string())
+        );
+        assert_eq!(
+            store.compute(&ColumnExpr::Arith(
+                ArithOp::Add,
+                Box::new(predicate),
+                int(1)
+            )),
+            Err("Arithmetic and comparisons need numeric operands".to_string())
+        );
+    }
+
+    #[test]
+    fn test_batches_commit_whole_and_recover_after_crash() {
+        let dir = tempdir().unwrap();
+        let config = || {
+            store_config(
+                dir.path(),
+                &[("ts", DataType::Int64), ("v", DataType::Float64)],
+            )
+        };
+        let row = |ts, v| vec![ScalarValue::Int64(ts), ScalarValue::Float64(v)];
+        let mut store = QStore::init(config());
+        store.create_index("ts").unwrap();
+
+        let mut batch = store.begin_batch().unwrap();
+        batch.put(row(1, 1.0)).unwrap();
+        batch.put(row(2, 2.0)).unwrap();
+        assert_eq!(
+            batch.put(vec![ScalarValue::Int64(3)]),
+            Err("Row has 1 values but the schema has 2 columns".to_string())
+        );
+        assert_eq!(
+            batch.put(vec![ScalarValue::Float64(3.0), ScalarValue::Float64(3.0)]),
+            Err("Column `ts` holds Int64 values, not Float64".to_string())
+        );
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.commit(), Ok(2));
+        assert_eq!(store.find("ts", ScalarValue::Int64(2)..), Ok(vec![1]));
+
+        let mut batch = store.begin_batch().unwrap();
+        batch.put(row(9, 9.0)).unwrap();
+        batch.rollback();
+        assert_eq!(store.memtable_row_count(), 2);
+        store.check_indexes().unwrap();
+        drop(store);
+
+        // A crash mid-commit: the marker is down and `ts` got one more row
+        let append = |name: &str, bytes: &[u8]| {
+            let mut file = OpenOptions::new()
+                .append(true)
+                .open(dir.path().join(name))
+                .unwrap();
+            file.write_all(bytes).unwrap();
+        };
+        std::fs::write(dir.path().join(txn::PENDING_FILE), "2\n").unwrap();
+        append("ts", &3i64.to_le_bytes());
+        let reader = QStore::open_read_only(config()).unwrap();
+        assert_eq!(reader.memtable_row_count(), 2);
+        let store = QStore::init(config());
+        assert_eq!(store.memtable_row_count(), 2);
+        assert!(!dir.path().join(txn::PENDING_FILE).exists());
+        assert_eq!(std::fs::metadata(dir.path().join("ts")).unwrap().len(), 16);
+        drop(store);
+
+        // A crash mid-put: one column has the row, the other a partial value
+        append("ts", &3i64.to_le_bytes());
+        append("v", &[0, 0, 0]);
+        let mut store = QStore::init(config());
+        assert_eq!(store.memtable_row_count(), 2);
+        assert_eq!(std::fs::metadata(dir.path().join("v")).unwrap().len(), 16);
+        store.put(row(3, 3.0));
+        assert_eq!(store.get(2), Some(row(3, 3.0)));
+        drop(store);
+        assert_eq!(QStore::init(config()).get(2), Some(row(3, 3.0)));
+    }
 }
@@ -0,0 +1,336 @@
+//! A minimal query language over a store's columns:
+//!
+//! ```text
+//! select count(*), avg(v), max(v) [from m]
+//!     [where ts between 10 and 20 and v >= 0.5 and host <> 3]
+//!     [group by host]
+//! ```
+//!
+//! Aggregates are `count`, `sum`, `avg`, `min` and `max`. With `group by`
+//! the grouping column may also be selected bare. A store holds a single
+//! table, so the `from` name is accepted but not checked. Predicates go
+//! through [`QStore::find`], so indexed columns are not scanned, and
+//! compare floats as [`QStore::filter`] does: -0.0 equals 0.0 and nothing
+//! compares true with NaN, so NaN is unequal (`<>` or `!=`) to everything.
+
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use arrow2::datatypes::DataType;
+
+use crate::index::IndexKey;
+use crate::{QStore, ScalarValue, as_f64};
+
+/// Column names and rows of a query result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<ScalarValue>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(Debug)]
+enum Item {
+    /// The grouping column
+    Column(String),
+    /// `None` is `count(*)`
+    Aggregate(Aggregate, Option<String>),
+}
+
+/// Splits a query into words, numbers and symbols.
+fn tokenize(query: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_alphanumeric() || "_.-".contains(c) {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                // `-` only starts a number or follows an exponent
+                let sign = c == '-' && (word.is_empty() || word.ends_with(['e', 'E']));
+                if !(c.is_alphanumeric() || "_.".contains(c) || sign) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(word);
+        } else if "<>!".contains(c) {
+            chars.next();
+            match chars.peek() {
+                Some('=') => {
+                    chars.next();
+                    tokens.push(format!("{}=", c));
+                }
+                Some('>') if c == '<' => {
+                    chars.next();
+                    tokens.push("<>".to_string());
+                }
+                _ if c == '!' => return Err("Unexpected `!` in query".to_string()),
+                _ => tokens.push(c.to_string()),
+            }
+        } else if "(),*=".contains(c) {
+            chars.next();
+            tokens.push(c.to_string());
+        } else {
+            return Err(format!("Unexpected `{}` in query", c));
+        }
+    }
+    Ok(tokens)
+}
+
+/// A cursor over query tokens.
+struct Tokens {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Tokens {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or("Unexpected end of query")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    /// Consume the next token if it is `word`, ignoring case.
+    fn eat(&mut self, word: &str) -> bool {
+        let matched = self.peek().is_some_and(|t| t.eq_ignore_ascii_case(word));
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        if self.eat(word) {
+            return Ok(());
+        }
+        match self.peek() {
+            Some(token) => Err(format!("Expected `{}`, found `{}`", word, token)),
+            None => Err(format!("Expected `{}` at end of query", word)),
+        }
+    }
+}
+
+fn aggregate(name: &str) -> Option<Aggregate> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "count" => Aggregate::Count,
+        "sum" => Aggregate::Sum,
+        "avg" => Aggregate::Avg,
+        "min" => Aggregate::Min,
+        "max" => Aggregate::Max,
+        _ => return None,
+    })
+}
+
+fn is_nan(value: &ScalarValue) -> bool {
+    matches!(value, ScalarValue::Float64(v) if v.is_nan())
+}
+
+/// Fold `values` with `agg`. Over no values, `avg`, `min` and `max` are NaN.
+fn fold(agg: Aggregate, values: &[&ScalarValue]) -> Result<ScalarValue, String> {
+    let ints: Option<Vec<i64>> = values
+        .iter()
+        .map(|v| match v {
+            ScalarValue::Int64(i) => Some(*i),
+            ScalarValue::Float64(_) => None,
+        })
+        .collect();
+    let floats = || values.iter().map(|v| as_f64(v));
+    Ok(match (agg, ints) {
+        (Aggregate::Count, _) => ScalarValue::Int64(values.len() as i64),
+        (Aggregate::Sum, Some(ints)) => ScalarValue::Int64(
+            ints.iter()
+                .try_fold(0i64, |sum, i| sum.checked_add(*i))
+                .ok_or("Integer overflow in sum")?,
+        ),
+        (Aggregate::Sum, None) => ScalarValue::Float64(floats().sum()),
+        (Aggregate::Avg, _) if values.is_empty() => ScalarValue::Float64(f64::NAN),
+        (Aggregate::Avg, _) => ScalarValue::Float64(floats().sum::<f64>() / values.len() as f64),
+        (Aggregate::Min | Aggregate::Max, _) if values.is_empty() => ScalarValue::Float64(f64::NAN),
+        (Aggregate::Min, Some(ints)) => ScalarValue::Int64(*ints.iter().min().unwrap()),
+        (Aggregate::Max, Some(ints)) => ScalarValue::Int64(*ints.iter().max().unwrap()),
+        (Aggregate::Min, None) => ScalarValue::Float64(floats().fold(f64::INFINITY, f64::min)),
+        (Aggregate::Max, None) => ScalarValue::Float64(floats().fold(f64::NEG_INFINITY, f64::max)),
+    })
+}
+
+impl QStore {
+    /// Parse `literal` as a value of `column`'s type.
+    fn literal(&self, column: &str, literal: &str) -> Result<ScalarValue, String> {
+        let field = self.field(column)?;
+        let value = match field.data_type {
+            DataType::Int64 => literal.parse().ok().map(ScalarValue::Int64),
+            _ => literal.parse().ok().map(ScalarValue::Float64),
+        };
+        value.ok_or_else(|| {
+            format!(
+                "`{}` is not a valid {:?} for column `{}`",
+                literal, field.data_type, column
+            )
+        })
+    }
+
+    /// Rows matching one predicate: `col between a and b` or `col op v`.
+    fn predicate(&self, tokens: &mut Tokens) -> Result<Vec<usize>, String> {
+        let column = tokens.next()?;
+        let op = tokens.next()?;
+        if op.eq_ignore_ascii_case("between") {
+            let low = self.literal(&column, &tokens.next()?)?;
+            tokens.expect("and")?;
+            let high = self.literal(&column, &tokens.next()?)?;
+            if is_nan(&low) || is_nan(&high) {
+                return Ok(Vec::new());
+            }
+            return self.find(&column, low..=high);
+        }
+        let value = self.literal(&column, &tokens.next()?)?;
+        let unequal = op == "<>" || op == "!=";
+        let (start, end) = match op.as_str() {
+            "=" | "<>" | "!=" => (Bound::Included(&value), Bound::Included(&value)),
+            "<" => (Bound::Unbounded, Bound::Excluded(&value)),
+            "<=" => (Bound::Unbounded, Bound::Included(&value)),
+            ">" => (Bound::Excluded(&value), Bound::Unbounded),
+            ">=" => (Bound::Included(&value), Bound::Unbounded),
+            _ => return Err(format!("Unknown comparison `{}`", op)),
+        };
+        // As in `filter`, no value compares true with NaN
+        let rows = match is_nan(&value) {
+            true => Vec::new(),
+            false => self.find(&column, (start.cloned(), end.cloned()))?,
+        };
+        if unequal {
+            return Ok((0..self.memtable_row_count())
+                .filter(|row| rows.binary_search(row).is_err())
+                .collect());
+        }
+        Ok(rows)
+    }
+
+    /// Run a `select` query; see the [module docs](self) for the syntax.
+    pub fn query(&self, query: &str) -> Result<QueryResult, String> {
+        let mut tokens = Tokens {
+            tokens: tokenize(query)?,
+            pos: 0,
+        };
+        tokens.expect("select")?;
+        let mut items = Vec::new();
+        loop {
+            let name = tokens.next()?;
+            let item = match aggregate(&name) {
+                Some(agg) if tokens.eat("(") => {
+                    let column = tokens.next()?;
+                    tokens.expect(")")?;
+                    match column.as_str() {
+                        "*" if agg == Aggregate::Count => Item::Aggregate(agg, None),
+                        "*" => return Err(format!("`{}(*)` is not supported", name)),
+                        _ => Item::Aggregate(agg, Some(column)),
+                    }
+                }
+                _ => Item::Column(name),
+            };
+            items.push(item);
+            if !tokens.eat(",") {
+                break;
+            }
+        }
+        if tokens.eat("from") {
+            tokens.next()?;
+        }
+
+        let mut rows: Vec<usize> = (0..self.memtable_row_count()).collect();
+        if tokens.eat("where") {
+            loop {
+                let matched = self.predicate(&mut tokens)?;
+                rows.retain(|row| matched.binary_search(row).is_ok());
+                if !tokens.eat("and") {
+                    break;
+                }
+            }
+        }
+        let group = if tokens.eat("group") {
+            tokens.expect("by")?;
+            Some(tokens.next()?)
+        } else {
+            None
+        };
+        if let Some(token) = tokens.peek() {
+            return Err(format!("Unexpected `{}` in query", token));
+        }
+
+        let mut columns = Vec::new();
+        for item in &items {
+            columns.push(match item {
+                Item::Column(name) if Some(name) == group.as_ref() => name.clone(),
+                Item::Column(name) => {
+                    return Err(format!(
+                        "Column `{}` must be aggregated or grouped by",
+                        name
+                    ));
+                }
+                Item::Aggregate(agg, name) => {
+                    if let Some(name) = name {
+                        self.column(name)?;
+                    }
+                    format!(
+                        "{}({})",
+                        format!("{:?}", agg).to_lowercase(),
+                        name.as_deref().unwrap_or("*")
+                    )
+                }
+            });
+        }
+
+        // Group key -> rows; without `group by`, one group of every row
+        let mut groups: BTreeMap<Option<IndexKey>, Vec<usize>> = BTreeMap::new();
+        match &group {
+            Some(name) => {
+                let keys = self.column(name)?;
+                for row in rows {
+                    let key = IndexKey::new(&keys[row]);
+                    groups.entry(Some(key)).or_default().push(row);
+                }
+            }
+            None => {
+                groups.insert(None, rows);
+            }
+        }
+        let mut result = Vec::new();
+        for (key, rows) in groups {
+            let mut out = Vec::new();
+            for item in &items {
+                out.push(match item {
+                    Item::Column(_) => key.clone().unwrap().0,
+                    Item::Aggregate(_, None) => ScalarValue::Int64(rows.len() as i64),
+                    Item::Aggregate(agg, Some(name)) => {
+                        let values = self.column(name)?;
+                        let values: Vec<&ScalarValue> = rows.iter().map(|&r| &values[r]).collect();
+                        fold(*agg, &values)?
+                    }
+                });
+            }
+            result.push(out);
+        }
+        Ok(QueryResult {
+            columns,
+            rows: result,
+        })
+    }
+}
@@ -0,0 +1,124 @@
+//! Retention: dropping rows that have aged out of a time window.
+//!
+//! Expiry writes each column without the expired rows beside the
+//! original, puts down [`EXPIRING_FILE`], renames the new files over the
+//! old ones, syncs the directory so the renames are durable and removes
+//! the marker. If the process dies in between, the
+//! next open finds the marker and finishes the renames, or, without the
+//! marker, deletes the new files; either way every column has the same
+//! rows.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::{QStore, ScalarValue, value_bytes};
+
+/// Name of the marker present while expired column files are being
+/// swapped in.
+pub const EXPIRING_FILE: &str = "EXPIRING";
+
+/// Make the renames in `dir` durable.
+pub(crate) fn sync_dir(dir: &Path) -> Result<(), String> {
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|e| e.to_string())
+}
+
+/// Where the rewritten `column` is written before it is swapped in.
+pub(crate) fn expiring_path(data_dir: &Path, column: &str) -> PathBuf {
+    data_dir.join(format!("{}.expiring", column))
+}
+
+/// What an expiry removed, or would remove in a dry run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionReport {
+    /// Rows older than the cutoff
+    pub rows: usize,
+    /// Bytes of column data those rows occupy on disk
+    pub bytes: u64,
+    /// Whether the rows were kept because this was a dry run
+    pub dry_run: bool,
+}
+
+impl QStore {
+    /// Drop every row whose Int64 `time_column` is below `cutoff`,
+    /// including rows appended out of time order. With `dry_run` set, only
+    /// reports what would be dropped.
+    pub fn expire_before(
+        &mut self,
+        time_column: &str,
+        cutoff: i64,
+        dry_run: bool,
+    ) -> Result<RetentionReport, String> {
+        let field = self.field(time_column)?;
+        if field.data_type != arrow2::datatypes::DataType::Int64 {
+            return Err(format!(
+                "Time column `{}` must hold Int64 values",
+                time_column
+            ));
+        }
+        let keep: Vec<bool> = self.columns[time_column]
+            .iter()
+            .map(|value| !matches!(value, ScalarValue::Int64(t) if *t < cutoff))
+            .collect();
+        let rows = keep.iter().filter(|kept| !**kept).count();
+        let report = RetentionReport {
+            rows,
+            bytes: (rows * 8 * self.columns.len()) as u64,
+            dry_run,
+        };
+        if dry_run || rows == 0 {
+            return Ok(report);
+        }
+        if self.is_read_only() {
+            return Err("Cannot expire rows from a read-only store".to_string());
+        }
+        let data_dir = &self.config.data_dir;
+        let fields = &self.config.schema.fields;
+        // Write every column's kept rows beside it before touching any
+        let written = fields.iter().try_for_each(|field| {
+            let bytes: Vec<u8> = self.columns[&field.name]
+                .iter()
+                .zip(&keep)
+                .filter(|(_, kept)| **kept)
+                .flat_map(|(value, _)| value_bytes(value))
+                .collect();
+            let mut file = File::create(expiring_path(data_dir, &field.name))?;
+            file.write_all(&bytes)?;
+            file.sync_all()
+        });
+        if let Err(e) = written {
+            for field in fields {
+                let _ = std::fs::remove_file(expiring_path(data_dir, &field.name));
+            }
+            return Err(e.to_string());
+        }
+        // From here the marker makes the next open finish the swap
+        let marker = data_dir.join(EXPIRING_FILE);
+        let mut file = File::create(&marker).map_err(|e| e.to_string())?;
+        file.write_all(format!("{}\n", self.memtable_row_count() - rows).as_bytes())
+            .and_then(|()| file.sync_all())
+            .map_err(|e| e.to_string())?;
+        for field in fields {
+            std::fs::rename(
+                expiring_path(data_dir, &field.name),
+                data_dir.join(&field.name),
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        sync_dir(data_dir)?;
+        std::fs::remove_file(&marker).map_err(|e| e.to_string())?;
+
+        for column in self.columns.values_mut() {
+            let mut kept = keep.iter();
+            column.retain(|_| *kept.next().unwrap());
+        }
+        // Row ids have shifted
+        let indexed: Vec<String> = self.indexes.keys().cloned().collect();
+        for name in indexed {
+            self.create_index(&name)?;
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,139 @@
+//! Downsampling: summarizing raw points into fixed-width time buckets.
+//!
+//! A rollup store holds one row per bucket with the columns of
+//! [`rollup_schema`], so coarse queries can read it instead of the raw
+//! points. [`QStore::rollup_into`] is incremental: it only summarizes
+//! buckets after the last one already in the rollup store.
+
+use arrow2::datatypes::{DataType, Field, Schema};
+
+use crate::{QStore, ScalarValue, as_f64};
+
+/// Summary of the points in one time bucket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rollup {
+    /// Start of the bucket, a multiple of the bucket width
+    pub bucket: i64,
+    pub count: i64,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+}
+
+impl Rollup {
+    /// This summary as a row of [`rollup_schema`].
+    pub fn row(&self) -> Vec<ScalarValue> {
+        vec![
+            ScalarValue::Int64(self.bucket),
+            ScalarValue::Int64(self.count),
+            ScalarValue::Float64(self.min),
+            ScalarValue::Float64(self.max),
+            ScalarValue::Float64(self.avg),
+        ]
+    }
+}
+
+/// Schema of a rollup store: `bucket, count, min, max, avg`.
+pub fn rollup_schema() -> Schema {
+    Schema {
+        fields: vec![
+            Field::new("bucket", DataType::Int64, false),
+            Field::new("count", DataType::Int64, false),
+            Field::new("min", DataType::Float64, false),
+            Field::new("max", DataType::Float64, false),
+            Field::new("avg", DataType::Float64, false),
+        ],
+        metadata: Default::default(),
+    }
+}
+
+impl QStore {
+    /// Summarize `value_column` per `width`-wide bucket of the Int64
+    /// `time_column`, in bucket order. Only buckets that start at or after
+    /// `from` are included. Fails if a time's bucket would start before
+    /// `i64::MIN`.
+    pub fn rollup(
+        &self,
+        time_column: &str,
+        value_column: &str,
+        width: i64,
+        from: i64,
+    ) -> Result<Vec<Rollup>, String> {
+        if width <= 0 {
+            return Err("Rollup bucket width must be positive".to_string());
+        }
+        let (times, values) = (self.column(time_column)?, self.column(value_column)?);
+        // Bucket start -> (count, min, max, sum)
+        let mut buckets: std::collections::BTreeMap<i64, (i64, f64, f64, f64)> = Default::default();
+        for (time, value) in times.iter().zip(values) {
+            let ScalarValue::Int64(time) = time else {
+                return Err(format!(
+                    "Time column `{}` must hold Int64 values",
+                    time_column
+                ));
+            };
+            let bucket = time.checked_sub(time.rem_euclid(width)).ok_or_else(|| {
+                format!(
+                    "Time {} is in a {}-wide bucket that starts before the earliest Int64 time",
+                    time, width
+                )
+            })?;
+            if bucket < from {
+                continue;
+            }
+            let value = as_f64(value);
+            let (count, min, max, sum) = buckets.entry(bucket).or_insert((0, value, value, 0.0));
+            *count += 1;
+            *min = min.min(value);
+            *max = max.max(value);
+            *sum += value;
+        }
+        Ok(buckets
+            .into_iter()
+            .map(|(bucket, (count, min, max, sum))| Rollup {
+                bucket,
+                count,
+                min,
+                max,
+                avg: sum / count as f64,
+            })
+            .collect())
+    }
+
+    /// Append to `dest` (a store with [`rollup_schema`]) the buckets of
+    /// `value_column` that are complete, i.e. end at or before `now`, and
+    /// newer than the last bucket already in `dest`. Returns how many
+    /// buckets were written. The buckets are appended as one
+    /// [`WriteBatch`](crate::txn::WriteBatch), so on any error, including
+    /// a read-only `dest` or one without the rollup schema, nothing is
+    /// written.
+    pub fn rollup_into(
+        &self,
+        dest: &mut QStore,
+        time_column: &str,
+        value_column: &str,
+        width: i64,
+        now: i64,
+    ) -> Result<usize, String> {
+        if dest.config.schema != rollup_schema() {
+            return Err("Destination store does not have the rollup schema".to_string());
+        }
+        let last = match dest.columns["bucket"].last() {
+            Some(ScalarValue::Int64(last)) => Some(*last),
+            _ => None,
+        };
+        let mut batch = dest.begin_batch()?;
+        let rollups = self.rollup(time_column, value_column, width, last.unwrap_or(i64::MIN))?;
+        for rollup in rollups {
+            if last.is_some_and(|last| rollup.bucket <= last) {
+                continue;
+            }
+            // A bucket ending past `i64::MAX` is never complete
+            if rollup.bucket.checked_add(width).is_none_or(|end| end > now) {
+                break;
+            }
+            batch.put(rollup.row())?;
+        }
+        batch.commit()
+    }
+}
@@ -0,0 +1,109 @@
+//! Inverted index from tags to the series that carry them.
+//!
+//! Each distinct tag string (e.g. `"host=a"`) is dictionary-encoded to a
+//! small integer once, and every tag maps to the sorted ids of its series,
+//! so a query intersects a few posting lists instead of scanning every
+//! series' tags.
+
+use std::collections::HashMap;
+
+use crate::TagSet;
+
+/// Identifies a series; the `measurement_id` of its [`crate::TsKey`]s.
+pub type SeriesId = u64;
+
+/// Tag dictionary plus posting lists.
+#[derive(Debug, Default)]
+pub struct TagIndex {
+    /// Tag string by code
+    tags: Vec<String>,
+    /// Code of each tag string
+    codes: HashMap<String, u32>,
+    /// Sorted series ids by tag code
+    postings: Vec<Vec<SeriesId>>,
+    /// Tag codes of each series
+    series: HashMap<SeriesId, Vec<u32>>,
+}
+
+impl TagIndex {
+    pub fn new() -> TagIndex {
+        TagIndex::default()
+    }
+
+    fn encode(&mut self, tag: &str) -> u32 {
+        if let Some(&code) = self.codes.get(tag) {
+            return code;
+        }
+        let code = self.tags.len() as u32;
+        self.tags.push(tag.to_string());
+        self.codes.insert(tag.to_string(), code);
+        self.postings.push(Vec::new());
+        code
+    }
+
+    /// Record the tags of `series`, replacing any it had before.
+    pub fn insert(&mut self, series: SeriesId, tags: &TagSet) {
+        self.remove(series);
+        let mut codes: Vec<u32> = tags.iter().map(|tag| self.encode(tag)).collect();
+        codes.sort_unstable();
+        codes.dedup();
+        for &code in &codes {
+            let posting = &mut self.postings[code as usize];
+            if let Err(pos) = posting.binary_search(&series) {
+                posting.insert(pos, series);
+            }
+        }
+        self.series.insert(series, codes);
+    }
+
+    /// Forget `series`, returning whether it was indexed. Tag strings stay
+    /// in the dictionary.
+    pub fn remove(&mut self, series: SeriesId) -> bool {
+        let Some(codes) = self.series.remove(&series) else {
+            return false;
+        };
+        for code in codes {
+            let posting = &mut self.postings[code as usize];
+            if let Ok(pos) = posting.binary_search(&series) {
+                posting.remove(pos);
+            }
+        }
+        true
+    }
+
+    /// The tags of `series`, sorted, or `None` if it is not indexed.
+    pub fn tags(&self, series: SeriesId) -> Option<TagSet> {
+        let codes = self.series.get(&series)?;
+        let mut tags: TagSet = codes
+            .iter()
+            .map(|&code| self.tags[code as usize].clone())
+            .collect();
+        tags.sort();
+        Some(tags)
+    }
+
+    /// Ids, ascending, of the series carrying every tag in `tags`. An
+    /// empty query matches every series.
+    pub fn query(&self, tags: &[&str]) -> Vec<SeriesId> {
+        let mut postings = Vec::with_capacity(tags.len());
+        for tag in tags {
+            match self.codes.get(*tag) {
+                Some(&code) => postings.push(&self.postings[code as usize]),
+                // An unknown tag matches nothing
+                None => return Vec::new(),
+            }
+        }
+        // Intersect starting from the shortest list
+        postings.sort_by_key(|posting| posting.len());
+        let Some((first, rest)) = postings.split_first() else {
+            let mut all: Vec<SeriesId> = self.series.keys().copied().collect();
+            all.sort_unstable();
+            return all;
+        };
+        first
+            .iter()
+            .copied()
+            .filter(|series| rest.iter().all(|p| p.binary_search(series).is_ok()))
+            .collect()
+    }
+}
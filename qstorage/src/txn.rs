@@ -0,0 +1,188 @@
+//! All-or-nothing ingest of several rows.
+//!
+//! A [`WriteBatch`] stages rows in memory. On commit the row count before
+//! the batch is written to [`PENDING_FILE`], every column file is appended
+//! and synced, and the marker is removed. If the process dies in between,
+//! the next open finds the marker and cuts every column back to that
+//! count, so either the whole batch is visible or none of it is.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::retention::{EXPIRING_FILE, expiring_path, sync_dir};
+use crate::{QStore, ScalarValue, index, load_column, value_bytes};
+
+/// Name of the marker holding the committed row count while a batch is
+/// being written.
+pub const PENDING_FILE: &str = "PENDING";
+
+/// Rows staged by [`QStore::begin_batch`]. Dropping the batch without
+/// committing it discards the rows.
+pub struct WriteBatch<'a> {
+    store: &'a mut QStore,
+    rows: Vec<Vec<ScalarValue>>,
+}
+
+/// The row count recorded by an interrupted commit, if any.
+pub(crate) fn pending_rows(data_dir: &Path) -> Result<Option<usize>, String> {
+    let path = data_dir.join(PENDING_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(text) => text
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Unreadable row count in {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Cut every column file in `data_dir` to `rows` values.
+fn truncate_files(store: &QStore, rows: usize) -> Result<(), String> {
+    for field in &store.config.schema.fields {
+        let file = OpenOptions::new()
+            .write(true)
+            .open(store.config.data_dir.join(&field.name))
+            .map_err(|e| e.to_string())?;
+        if file.metadata().map_err(|e| e.to_string())?.len() != rows as u64 * 8 {
+            file.set_len(rows as u64 * 8).map_err(|e| e.to_string())?;
+            file.sync_all().map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+impl QStore {
+    /// Bring a store opened for writing back to whole rows: finish or undo
+    /// an interrupted expiry (see [`retention`](crate::retention)), roll
+    /// back an interrupted batch, and drop a row or partial value that was
+    /// only written to some columns.
+    pub(crate) fn recover(&mut self) -> Result<(), String> {
+        let data_dir = &self.config.data_dir;
+        let expiring = data_dir.join(EXPIRING_FILE);
+        let swapping = expiring.exists();
+        for field in &self.config.schema.fields {
+            let path = expiring_path(data_dir, &field.name);
+            if !path.exists() {
+                continue;
+            }
+            if swapping {
+                std::fs::rename(&path, data_dir.join(&field.name)).map_err(|e| e.to_string())?;
+                let column = load_column(&data_dir.join(&field.name), &field.data_type);
+                self.columns.insert(field.name.clone(), column);
+            } else {
+                std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+            }
+        }
+        if swapping {
+            sync_dir(data_dir)?;
+            std::fs::remove_file(&expiring).map_err(|e| e.to_string())?;
+        }
+
+        let aligned = self.columns.values().map(Vec::len).min().unwrap_or(0);
+        let pending = pending_rows(&self.config.data_dir)?;
+        let rows = pending.unwrap_or(aligned).min(aligned);
+        for column in self.columns.values_mut() {
+            column.truncate(rows);
+        }
+        truncate_files(self, rows)?;
+        if pending.is_some() {
+            std::fs::remove_file(self.config.data_dir.join(PENDING_FILE))
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Start a batch of rows that becomes visible, in memory and on disk,
+    /// only when committed.
+    pub fn begin_batch(&mut self) -> Result<WriteBatch<'_>, String> {
+        if self.is_read_only() {
+            return Err("Cannot write to a read-only store".to_string());
+        }
+        Ok(WriteBatch {
+            store: self,
+            rows: Vec::new(),
+        })
+    }
+}
+
+impl WriteBatch<'_> {
+    /// Stage `row`, checking it against the schema.
+    pub fn put(&mut self, row: Vec<ScalarValue>) -> Result<(), String> {
+        let fields = &self.store.config.schema.fields;
+        if row.len() != fields.len() {
+            return Err(format!(
+                "Row has {} values but the schema has {} columns",
+                row.len(),
+                fields.len()
+            ));
+        }
+        for (field, value) in fields.iter().zip(&row) {
+            index::check_type(field, value)?;
+        }
+        self.rows.push(row);
+        Ok(())
+    }
+
+    /// Number of staged rows.
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Discard the staged rows; the same as dropping the batch.
+    pub fn rollback(self) {}
+
+    /// Write the staged rows to every column and make them visible.
+    /// Returns how many rows were added. On error nothing is added.
+    pub fn commit(self) -> Result<usize, String> {
+        let WriteBatch { store, rows } = self;
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        let start = store.memtable_row_count();
+        let marker = store.config.data_dir.join(PENDING_FILE);
+        let mut file = File::create(&marker).map_err(|e| e.to_string())?;
+        file.write_all(format!("{}\n", start).as_bytes())
+            .and_then(|()| file.sync_all())
+            .map_err(|e| e.to_string())?;
+
+        let written = store
+            .config
+            .schema
+            .fields
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, field)| {
+                let bytes: Vec<u8> = rows.iter().flat_map(|row| value_bytes(&row[i])).collect();
+                let mut file = OpenOptions::new()
+                    .append(true)
+                    .open(store.config.data_dir.join(&field.name))?;
+                file.write_all(&bytes)?;
+                file.sync_all()
+            });
+        if let Err(e) = written {
+            // Undo what reached disk; if that fails too, the marker makes
+            // the next open finish the job
+            truncate_files(store, start)?;
+            let _ = std::fs::remove_file(&marker);
+            return Err(e.to_string());
+        }
+        std::fs::remove_file(&marker).map_err(|e| e.to_string())?;
+
+        let added = rows.len();
+        for (offset, row) in rows.into_iter().enumerate() {
+            for (field, value) in store.config.schema.fields.iter().zip(row) {
+                if let Some(index) = store.indexes.get_mut(&field.name) {
+                    index::insert(index, start + offset, &value);
+                }
+                store.columns.get_mut(&field.name).unwrap().push(value);
+            }
+        }
+        Ok(added)
+    }
+}
@@ -0,0 +1,176 @@
+//! REPL commands: input lines starting with `:`.
+
+use bumpalo::Bump;
+use qparser::Config;
+use qparser::inspect;
+use qparser::ops::{self, OPERATORS};
+
+use crate::record::Recorder;
+
+/// A parsed REPL command.
+#[derive(Debug, PartialEq)]
+pub enum Command<'a> {
+    /// `:help` or `:help <operator>`
+    Help(Option<&'a str>),
+    /// `:stats`, `:stats on` or `:stats off`
+    Stats(Option<&'a str>),
+    /// `:inspect <expr>`
+    Inspect(Option<&'a str>),
+    /// `:paste`, reading lines up to `:end` or Ctrl-D as one script
+    Paste,
+    /// `:record <file>`
+    Record(Option<&'a str>),
+    /// `:stop`, ending a recording
+    Stop,
+    /// Any other `:name`
+    Unknown(&'a str),
+}
+
+/// Parse `line` as a command, or return `None` if it is an expression.
+pub fn parse(line: &str) -> Option<Command<'_>> {
+    let rest = line.trim().strip_prefix(':')?;
+    let (name, arg) = match rest.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, Some(arg.trim()).filter(|a| !a.is_empty())),
+        None => (rest, None),
+    };
+    Some(match name {
+        "help" | "h" | "?" => Command::Help(arg),
+        "stats" => Command::Stats(arg),
+        "inspect" => Command::Inspect(arg),
+        "paste" => Command::Paste,
+        "record" => Command::Record(arg),
+        "stop" => Command::Stop,
+        _ => Command::Unknown(name),
+    })
+}
+
+/// Text for `:help`, either an operator summary or one operator's details.
+pub fn help(topic: Option<&str>) -> Result<String, String> {
+    match topic {
+        None => {
+            let mut out = String::from("Operators (use :help <op> for details):\n");
+            for op in OPERATORS {
+                out.push_str(&format!("  {:<4} {}\n", op.symbol, op.name));
+            }
+            Ok(out.trim_end().to_string())
+        }
+        Some(symbol) => {
+            let op = ops::lookup(symbol).ok_or_else(|| format!("No help for `{}`", symbol))?;
+            let mut out = format!(
+                "{} ({}, {} operand{}: {})\n  {}",
+                op.symbol,
+                op.name,
+                op.arity,
+                if op.arity == 1 { "" } else { "s" },
+                op.operands,
+                op.doc
+            );
+            if !op.aliases.is_empty() {
+                out.push_str(&format!("\n  Also written {}", op.aliases.join(" ")));
+            }
+            for (input, result) in op.examples {
+                out.push_str(&format!("\n  {} => {}", input, result));
+            }
+            Ok(out)
+        }
+    }
+}
+
+/// Apply `:stats [on|off]` to `enabled`, describing the new state.
+pub fn stats(arg: Option<&str>, enabled: &mut bool) -> Result<String, String> {
+    match arg {
+        Some("on") => *enabled = true,
+        Some("off") => *enabled = false,
+        None => (),
+        Some(other) => return Err(format!("Expected `on` or `off`, not `{}`", other)),
+    }
+    Ok(format!("Stats are {}", if *enabled { "on" } else { "off" }))
+}
+
+/// Text for `:inspect <expr>`: the result's type and the size of the
+/// expression and its value.
+pub fn inspect(input: Option<&str>, arena: &Bump, config: &Config) -> Result<String, String> {
+    let input = input.ok_or("Expected an expression to inspect")?;
+    inspect::inspect(arena, input, config).map(|found| found.to_string())
+}
+
+/// Start recording the session to `path`, ending any recording in progress.
+pub fn record(path: Option<&str>, recording: &mut Option<Recorder>) -> Result<String, String> {
+    let path = path.ok_or("Expected a file to record to")?;
+    let recorder = Recorder::create(path.as_ref())?;
+    let text = format!("Recording to {}", recorder.path().display());
+    *recording = Some(recorder);
+    Ok(text)
+}
+
+/// Stop the recording in progress.
+pub fn stop(recording: &mut Option<Recorder>) -> Result<String, String> {
+    let recorder = recording.take().ok_or("Not recording")?;
+    Ok(format!(
+        "Stopped recording to {}",
+        recorder.path().display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(parse("1+2"), None);
+        assert_eq!(parse(":help"), Some(Command::Help(None)));
+        assert_eq!(parse("  :help  <= "), Some(Command::Help(Some("<="))));
+        assert_eq!(parse(":stats on"), Some(Command::Stats(Some("on"))));
+        assert_eq!(
+            parse(":inspect 1 + 2"),
+            Some(Command::Inspect(Some("1 + 2")))
+        );
+        assert_eq!(parse(":paste"), Some(Command::Paste));
+        assert_eq!(
+            parse(":record out.txt"),
+            Some(Command::Record(Some("out.txt")))
+        );
+        assert_eq!(parse(":stop"), Some(Command::Stop));
+        assert_eq!(parse(":nope"), Some(Command::Unknown("nope")));
+    }
+
+    #[test]
+    fn test_help_for_operator() {
+        let text = help(Some("+")).unwrap();
+        assert!(text.starts_with("+ (add, 2 operands: numbers)"), "{text}");
+        let text = help(Some("abs")).unwrap();
+        assert!(
+            text.starts_with("abs (absolute value, 1 operand: numbers)"),
+            "{text}"
+        );
+        let text = help(Some("≤")).unwrap();
+        assert!(
+            text.starts_with("<= (less or equal, 2 operands: atoms)\n  1b unless the left atom sorts after the right one.\n  Also written ≤\n"),
+            "{text}"
+        );
+        assert!(help(Some("%")).is_err());
+    }
+
+    #[test]
+    fn test_stats_toggle() {
+        let mut enabled = false;
+        assert_eq!(
+            stats(Some("on"), &mut enabled),
+            Ok("Stats are on".to_string())
+        );
+        assert!(enabled);
+        assert_eq!(stats(None, &mut enabled), Ok("Stats are on".to_string()));
+        assert!(stats(Some("maybe"), &mut enabled).is_err());
+        assert!(enabled);
+        stats(Some("off"), &mut enabled).unwrap();
+        assert!(!enabled);
+    }
+
+    #[test]
+    fn test_inspect() {
+        let text = inspect(Some("abs 3-4"), &Bump::new(), &Config::default()).unwrap();
+        assert!(text.starts_with("type int, 4 nodes, depth 3, "), "{text}");
+        assert!(inspect(None, &Bump::new(), &Config::default()).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//! Shell completion scripts generated from the clap definition, so
+//! completions stay in sync as options are added.
+
+use clap::ValueEnum;
+use clap_complete::generate;
+
+/// Shell to emit a completion script for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// The completion script for `cmd` in `shell`. Arguments with a
+/// `ValueHint` complete accordingly, e.g. file names for `FilePath`.
+pub fn emit(shell: Shell, mut cmd: clap::Command) -> String {
+    let name = cmd.get_name().to_string();
+    let mut out = Vec::new();
+    match shell {
+        Shell::Bash => generate(clap_complete::Shell::Bash, &mut cmd, &name, &mut out),
+        Shell::Zsh => generate(clap_complete::Shell::Zsh, &mut cmd, &name, &mut out),
+        Shell::Fish => generate(clap_complete::Shell::Fish, &mut cmd, &name, &mut out),
+    }
+    String::from_utf8(out).expect("completion scripts are UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ValueHint};
+
+    fn command() -> clap::Command {
+        clap::Command::new("q")
+            .arg(Arg::new("file").value_hint(ValueHint::FilePath))
+            .arg(
+                Arg::new("color")
+                    .long("color")
+                    .help("When's color used")
+                    .value_parser(["auto", "never"]),
+            )
+            .arg(
+                Arg::new("replay")
+                    .long("replay")
+                    .help("Transcript to replay")
+                    .value_hint(ValueHint::FilePath),
+            )
+            .arg(
+                Arg::new("exact")
+                    .long("exact")
+                    .action(clap::ArgAction::SetTrue),
+            )
+    }
+
+    #[test]
+    fn test_scripts_cover_every_option() {
+        let bash = emit(Shell::Bash, command());
+        assert!(bash.contains("--color --replay --exact"), "{bash}");
+        assert!(
+            bash.contains("COMPREPLY=($(compgen -W \"auto never\""),
+            "{bash}"
+        );
+        assert!(bash.contains("complete -F _q"), "{bash}");
+
+        let zsh = emit(Shell::Zsh, command());
+        assert!(zsh.contains(":(auto never)"), "{zsh}");
+        assert!(zsh.contains("'--exact["), "{zsh}");
+
+        let fish = emit(Shell::Fish, command());
+        assert!(fish.contains("-l color"), "{fish}");
+        assert!(fish.contains("-l exact"), "{fish}");
+    }
+
+    #[test]
+    fn test_file_path_options_complete_file_names() {
+        let bash = emit(Shell::Bash, command());
+        let replay = &bash[bash.find("--replay)").unwrap()..];
+        let case = &replay[..replay.find(";;").unwrap()];
+        assert!(case.contains("compgen -f"), "{case}");
+
+        let zsh = emit(Shell::Zsh, command());
+        assert!(
+            zsh.contains("'--replay=[Transcript to replay]: :_files'"),
+            "{zsh}"
+        );
+        assert!(zsh.contains(":file:_files'"), "{zsh}");
+
+        let fish = emit(Shell::Fish, command());
+        assert!(
+            fish.contains("complete -c q -l replay -d 'Transcript to replay' -r -F\n"),
+            "{fish}"
+        );
+    }
+}
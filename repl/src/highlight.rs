@@ -0,0 +1,202 @@
+//! Editor syntax definitions generated from the operator registry, so
+//! highlighting stays in sync as verbs are added.
+
+use clap::ValueEnum;
+use qparser::ops::OPERATORS;
+
+/// Grammar format to emit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HighlightFormat {
+    /// Vim syntax file (`syntax/q.vim`)
+    Vim,
+    /// TextMate grammar (JSON), also read by VS Code and Sublime Text
+    Tmlanguage,
+    /// Pygments lexer (a Python module defining `QLexer`)
+    Pygments,
+}
+
+/// Verbs written as words, e.g. `abs`.
+fn word_verbs() -> Vec<&'static str> {
+    OPERATORS
+        .iter()
+        .map(|op| op.symbol)
+        .filter(|s| s.chars().all(|c| c.is_ascii_alphabetic()))
+        .collect()
+}
+
+/// Verbs written as symbols, then their Unicode aliases, longest first
+/// so `<=` wins over `<`.
+fn symbol_verbs() -> Vec<&'static str> {
+    let mut symbols: Vec<&str> = OPERATORS
+        .iter()
+        .map(|op| op.symbol)
+        .filter(|s| !s.chars().all(|c| c.is_ascii_alphabetic()))
+        .chain(OPERATORS.iter().flat_map(|op| op.aliases.iter().copied()))
+        .collect();
+    symbols.sort_by_key(|s| std::cmp::Reverse(s.chars().count()));
+    symbols
+}
+
+/// Escape `s` for a regex, backslashing every character in `special`.
+fn escape(s: &str, special: &str) -> String {
+    s.chars()
+        .flat_map(|c| {
+            let slash = special.contains(c).then_some('\\');
+            slash.into_iter().chain(std::iter::once(c))
+        })
+        .collect()
+}
+
+/// The syntax definition in `format`.
+pub fn emit(format: HighlightFormat) -> String {
+    match format {
+        HighlightFormat::Vim => {
+            let operators: Vec<String> = symbol_verbs()
+                .into_iter()
+                .map(|s| escape(s, "*.[]~\\^$"))
+                .collect();
+            format!(
+                "\" Vim syntax file for Q; generated by `repl --emit-highlight vim`
+if exists(\"b:current_syntax\")
+  finish
+endif
+
+syn region qComment start=\"{{-\" end=\"-}}\" contains=qComment
+syn keyword qVerb {}
+syn match qNumber \"\\<\\d\\+\\(\\.\\d\\+\\)\\=i\\=\\>\"
+syn match qOperator \"{}\"
+
+hi def link qComment Comment
+hi def link qVerb Keyword
+hi def link qNumber Number
+hi def link qOperator Operator
+
+let b:current_syntax = \"q\"
+",
+                word_verbs().join(" "),
+                operators.join("\\|")
+            )
+        }
+        HighlightFormat::Tmlanguage => {
+            let operators: Vec<String> = symbol_verbs()
+                .into_iter()
+                .map(|s| escape(s, "*+?.|^$()[]{}\\"))
+                .collect();
+            // Backslashes are doubled again for the JSON string literals
+            let json = |regex: String| regex.replace('\\', "\\\\");
+            format!(
+                r##"{{
+  "name": "Q",
+  "scopeName": "source.q",
+  "fileTypes": ["q"],
+  "patterns": [
+    {{ "include": "#comment" }},
+    {{ "name": "keyword.operator.word.q", "match": "{}" }},
+    {{ "name": "constant.numeric.q", "match": "{}" }},
+    {{ "name": "keyword.operator.q", "match": "{}" }}
+  ],
+  "repository": {{
+    "comment": {{
+      "name": "comment.block.q",
+      "begin": "\\{{-",
+      "end": "-\\}}",
+      "patterns": [{{ "include": "#comment" }}]
+    }}
+  }}
+}}
+"##,
+                json(format!("\\b({})\\b", word_verbs().join("|"))),
+                json(r"\b\d+(\.\d+)?i?\b".to_string()),
+                json(operators.join("|"))
+            )
+        }
+        HighlightFormat::Pygments => {
+            let operators: Vec<String> = symbol_verbs()
+                .into_iter()
+                .map(|s| escape(s, "*+?.|^$()[]{}\\"))
+                .collect();
+            let verbs: Vec<String> = word_verbs().iter().map(|v| format!("'{}'", v)).collect();
+            format!(
+                r#"# Pygments lexer for Q; generated by `repl --emit-highlight pygments`
+from pygments.lexer import RegexLexer, words
+from pygments.token import Comment, Keyword, Number, Operator, Punctuation, Text
+
+
+class QLexer(RegexLexer):
+    name = 'Q'
+    aliases = ['q']
+    filenames = ['*.q']
+
+    tokens = {{
+        'root': [
+            (r'\{{-', Comment.Multiline, 'comment'),
+            (r'\s+', Text),
+            (words(({},), prefix=r'\b', suffix=r'\b'), Keyword),
+            (r'\b\d+(\.\d+)?i?\b', Number),
+            (r'{}', Operator),
+            (r'[()]', Punctuation),
+            (r'\w+|.', Text),
+        ],
+        'comment': [
+            (r'\{{-', Comment.Multiline, '#push'),
+            (r'-\}}', Comment.Multiline, '#pop'),
+            (r'[^{{}}-]+', Comment.Multiline),
+            (r'[{{}}-]', Comment.Multiline),
+        ],
+    }}
+"#,
+                verbs.join(", "),
+                operators.join("|")
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grammars_cover_every_operator() {
+        let vim = emit(HighlightFormat::Vim);
+        assert!(
+            vim.contains("syn keyword qVerb abs arg conj numerator denominator\n"),
+            "{vim}"
+        );
+        assert!(
+            vim.contains("syn match qOperator \"<=\\|>=\\|<>\\|\\*\\|/"),
+            "{vim}"
+        );
+
+        assert!(
+            vim.contains("syn region qComment start=\"{-\" end=\"-}\" contains=qComment\n"),
+            "{vim}"
+        );
+
+        let tm = emit(HighlightFormat::Tmlanguage);
+        assert!(
+            tm.contains(r#""match": "\\b(abs|arg|conj|numerator|denominator)\\b""#),
+            "{tm}"
+        );
+        assert!(
+            tm.contains(r#""match": "<=|>=|<>|\\*|/|\\+|-|<|>|=|&|\\||×|÷|−|≤|≥|≠|∧|∨""#),
+            "{tm}"
+        );
+
+        let py = emit(HighlightFormat::Pygments);
+        assert!(
+            py.contains(
+                "(words(('abs', 'arg', 'conj', 'numerator', 'denominator',), prefix=r'\\b', suffix=r'\\b'), Keyword),\n"
+            ),
+            "{py}"
+        );
+        assert!(
+            py.contains(r"(r'<=|>=|<>|\*|/|\+|-|<|>|=|&|\||×|÷|−|≤|≥|≠|∧|∨', Operator),"),
+            "{py}"
+        );
+        assert!(
+            py.contains("(r'\\{-', Comment.Multiline, '#push'),"),
+            "{py}"
+        );
+    }
+}
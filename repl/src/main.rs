@@ -1,31 +1,299 @@
+use clap::{CommandFactory, Parser, ValueHint};
 use rustyline::Editor;
 use rustyline::error::ReadlineError;
 
 use bumpalo::Bump;
-use qparser::eval_str;
+use qparser::{Config, EvalStats, eval_counted_in};
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut rl = Editor::<()>::new()?;
+mod commands;
+mod completions;
+mod highlight;
+mod record;
+mod script;
+mod stats;
+mod theme;
+mod watch;
+
+use std::path::PathBuf;
+use std::time::Instant;
+
+use commands::Command;
+use completions::Shell;
+use highlight::HighlightFormat;
+use script::{ErrorKind, Format};
+use theme::{ColorChoice, Style, Theme, ThemeName};
+
+/// Exit status of a script with a line that failed to evaluate.
+const EXIT_EVAL_ERROR: i32 = 1;
+/// Exit status of a script with a line that failed to parse; wins over
+/// evaluation errors.
+const EXIT_PARSE_ERROR: i32 = 2;
+/// Exit status for invalid command-line usage, or a script, transcript
+/// or terminal that cannot be read or set up.
+const EXIT_USAGE: i32 = 3;
+
+const EXIT_STATUS_HELP: &str =
+    "Exit status: 0 ok, 1 evaluation error, 2 parse error, 3 usage or I/O error";
+
+/// Interactive REPL for Q expressions.
+#[derive(Parser)]
+#[command(name = "repl", version, about, after_help = EXIT_STATUS_HELP)]
+struct Args {
+    /// Script to run (one expression per line) instead of starting the REPL;
+    /// `-` reads the script from stdin. In a `.md` file only fenced `q`
+    /// code blocks are run
+    #[arg(value_hint = ValueHint::FilePath)]
+    file: Option<PathBuf>,
+    /// Print the Markdown script with each `q` block's results after it
+    #[arg(long, requires = "file", conflicts_with = "watch")]
+    doc: bool,
+    /// Re-run the script whenever it changes
+    #[arg(long, requires = "file")]
+    watch: bool,
+    /// Show at most this many distinct script errors
+    #[arg(long, value_name = "N")]
+    max_diagnostics: Option<usize>,
+    /// Divide ints exactly, printing rationals (`10/4` is `5/2`)
+    #[arg(long)]
+    exact: bool,
+    /// When to colorize output (`NO_COLOR` is honored in auto mode)
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Color palette
+    #[arg(long, value_enum, default_value_t = ThemeName::Default)]
+    theme: ThemeName,
+    /// Print an editor syntax definition generated from the operator table
+    #[arg(long, value_enum, value_name = "FORMAT", exclusive = true)]
+    emit_highlight: Option<HighlightFormat>,
+    /// Print only results: no banners, and no script error messages (the
+    /// exit status still reports them)
+    #[arg(long, short, conflicts_with = "watch")]
+    quiet: bool,
+    /// Print one tab-separated record per script line, `LINE ok VALUE` or
+    /// `LINE error MESSAGE`, for other programs to read
+    #[arg(long, requires = "file", conflicts_with_all = ["doc", "watch", "quiet"])]
+    porcelain: bool,
+    /// Re-run the inputs of a transcript written by `:record` and report
+    /// the first result that differs
+    #[arg(long, value_name = "TRANSCRIPT", value_hint = ValueHint::FilePath, conflicts_with = "file")]
+    replay: Option<PathBuf>,
+    /// Print a shell completion script for this command
+    #[arg(long, value_enum, value_name = "SHELL", exclusive = true)]
+    completions: Option<Shell>,
+}
+
+fn main() {
+    let args = Args::try_parse().unwrap_or_else(|err| {
+        // `--help` and `--version` are reported as errors too, on stdout
+        let code = if err.use_stderr() { EXIT_USAGE } else { 0 };
+        let _ = err.print();
+        std::process::exit(code);
+    });
+    if let Err(err) = run(args) {
+        eprintln!("repl: {}", err);
+        std::process::exit(EXIT_USAGE);
+    }
+}
+
+/// Run the command; an error is an I/O or setup failure, reported with
+/// [`EXIT_USAGE`].
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(format) = args.emit_highlight {
+        print!("{}", highlight::emit(format));
+        return Ok(());
+    }
+    if let Some(shell) = args.completions {
+        print!("{}", completions::emit(shell, Args::command()));
+        return Ok(());
+    }
+    let theme = Theme::new(args.color, args.theme);
+    let config = Config {
+        exact: args.exact,
+        ..Config::default()
+    };
+    if let Some(path) = &args.replay {
+        match record::replay(path, &config) {
+            Ok(count) if !args.quiet => println!("Replayed {} inputs", count),
+            Ok(_) => (),
+            Err(diff) => {
+                eprintln!("{}", theme.paint(Style::Error, &diff));
+                std::process::exit(EXIT_EVAL_ERROR);
+            }
+        }
+        return Ok(());
+    }
+    if let Some(path) = &args.file {
+        let from_stdin = path.as_os_str() == "-";
+        if args.watch {
+            if from_stdin {
+                eprintln!("--watch needs a file, not stdin");
+                std::process::exit(EXIT_USAGE);
+            }
+            return watch::watch(path, &config, &theme, args.max_diagnostics);
+        }
+        let source = if from_stdin {
+            std::io::read_to_string(std::io::stdin()).map_err(|e| format!("<stdin>: {}", e))?
+        } else {
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?
+        };
+        let name = if from_stdin {
+            "<stdin>".to_string()
+        } else {
+            path.display().to_string()
+        };
+        if args.doc {
+            print!(
+                "{}",
+                script::render_markdown(&source, &mut Bump::new(), &config)
+            );
+            return Ok(());
+        }
+        let format = Format::of(path);
+        let mut results = Vec::new();
+        let diagnostics =
+            script::run_source(&source, format, &mut Bump::new(), &config, |line, val| {
+                if args.porcelain {
+                    results.push((line, val.to_string()));
+                } else {
+                    println!("{}", theme.paint(Style::Value, val))
+                }
+            });
+        if args.porcelain {
+            for record in script::render_porcelain(&results, &diagnostics) {
+                println!("{}", record);
+            }
+        } else if !args.quiet {
+            for line in script::render_diagnostics(&name, &diagnostics, args.max_diagnostics) {
+                eprintln!("{}", theme.paint(Style::Error, &line));
+            }
+        }
+        let status = diagnostics.iter().map(|d| match d.kind {
+            ErrorKind::Parse => EXIT_PARSE_ERROR,
+            ErrorKind::Eval => EXIT_EVAL_ERROR,
+        });
+        if let Some(code) = status.max() {
+            std::process::exit(code);
+        }
+        return Ok(());
+    }
+    let mut rl = Editor::<()>::new().map_err(|e| format!("Cannot start the line editor: {}", e))?;
+    // One arena reused across inputs; reset after each evaluation
+    let mut bump = Bump::new();
+    let mut show_stats = false;
+    let mut recording = None;
     loop {
         let line = rl.readline(">> ");
         match line {
             Ok(input) => {
                 rl.add_history_entry(input.as_str());
-                let _bump = Bump::new();
-                match eval_str(&input) {
-                    Ok(val) => println!("=> {}", val),
-                    Err(err) => eprintln!("Error: {}", err),
+                if let Some(command) = commands::parse(&input) {
+                    let result = match command {
+                        Command::Paste => {
+                            paste(&mut rl, &mut bump, &config, &theme, args.quiet)?;
+                            continue;
+                        }
+                        Command::Help(topic) => commands::help(topic),
+                        Command::Stats(arg) => commands::stats(arg, &mut show_stats),
+                        Command::Inspect(arg) => commands::inspect(arg, &bump, &config),
+                        Command::Record(path) => commands::record(path, &mut recording),
+                        Command::Stop => commands::stop(&mut recording),
+                        Command::Unknown(name) => Err(format!("Unknown command :{}", name)),
+                    };
+                    match result {
+                        Ok(text) => println!("{}", text),
+                        Err(err) => eprintln!("{}", theme.paint(Style::Error, &err)),
+                    }
+                    bump.reset();
+                    continue;
+                }
+                let counted = EvalStats::default();
+                let allocations_before = stats::allocations();
+                let start = Instant::now();
+                let result = eval_counted_in(&bump, &input, &config, &counted);
+                let elapsed = start.elapsed();
+                let allocations = allocations_before.zip(stats::allocations()).map(
+                    |((count, bytes), (count_after, bytes_after))| {
+                        (count_after - count, bytes_after - bytes)
+                    },
+                );
+                if let Some(recorder) = &mut recording {
+                    let shown = result
+                        .as_ref()
+                        .map(|val| val.to_string())
+                        .map_err(Clone::clone);
+                    if let Err(err) = recorder.entry(&input, &shown, elapsed) {
+                        eprintln!(
+                            "{}",
+                            theme.paint(Style::Error, &format!("Recording stopped: {}", err))
+                        );
+                        recording = None;
+                    }
                 }
+                match result {
+                    Ok(val) => println!("=> {}", theme.paint(Style::Value, &val.to_string())),
+                    Err(err) => {
+                        eprintln!("{}", theme.paint(Style::Error, &format!("Error: {}", err)))
+                    }
+                }
+                if show_stats {
+                    let report = stats::Report {
+                        elapsed,
+                        steps: counted.steps(),
+                        depth: counted.max_depth(),
+                        allocations,
+                    };
+                    eprintln!("{}", report);
+                }
+                bump.reset();
             }
             Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
-                println!("Exiting.");
+                if !args.quiet {
+                    println!("Exiting.");
+                }
                 break;
             }
-            Err(err) => {
-                eprintln!("Error reading line: {:?}", err);
-                break;
+            Err(err) => return Err(format!("Error reading line: {}", err).into()),
+        }
+    }
+    Ok(())
+}
+
+/// Read lines up to `:end` or Ctrl-D and run them together as a plain
+/// script, so a pasted snippet (block comments included) is taken whole.
+/// Ctrl-C discards what was pasted.
+fn paste(
+    rl: &mut Editor<()>,
+    arena: &mut Bump,
+    config: &Config,
+    theme: &Theme,
+    quiet: bool,
+) -> rustyline::Result<()> {
+    if !quiet {
+        println!("Paste mode; finish with :end or Ctrl-D");
+    }
+    let mut source = String::new();
+    loop {
+        match rl.readline(".. ") {
+            Ok(line) if line.trim() == ":end" => break,
+            Ok(line) => {
+                source.push_str(&line);
+                source.push('\n');
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(ReadlineError::Interrupted) => {
+                if !quiet {
+                    println!("Paste discarded");
+                }
+                return Ok(());
             }
+            Err(err) => return Err(err),
         }
     }
+    let diagnostics = script::run_source(&source, Format::Plain, arena, config, |_, val| {
+        println!("=> {}", theme.paint(Style::Value, val))
+    });
+    for line in script::render_diagnostics("<paste>", &diagnostics, None) {
+        eprintln!("{}", theme.paint(Style::Error, &line));
+    }
     Ok(())
 }
@@ -0,0 +1,124 @@
+//! Session transcripts: `:record <file>` writes each input and its result
+//! as the golden transcripts in `qparser::golden` do, plus a `# ` line
+//! with its timing, and `--replay <file>` re-runs the inputs and reports
+//! the first result that changed.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use qparser::Config;
+use qparser::golden::{first_difference, transcript};
+
+/// Prefix of annotation lines, which replay ignores.
+const NOTE: &str = "# ";
+
+/// An open transcript file.
+pub struct Recorder {
+    path: PathBuf,
+    file: File,
+}
+
+impl Recorder {
+    /// Start a transcript at `path`, replacing any file there.
+    pub fn create(path: &Path) -> Result<Recorder, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Recorder {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append one evaluated input. Blank inputs are skipped, as replay
+    /// skips them.
+    pub fn entry(
+        &mut self,
+        input: &str,
+        result: &Result<String, String>,
+        elapsed: Duration,
+    ) -> Result<(), String> {
+        if input.trim().is_empty() {
+            return Ok(());
+        }
+        let outcome = match result {
+            Ok(value) => format!("=> {}", value),
+            Err(err) => format!("Error: {}", err),
+        };
+        writeln!(
+            self.file,
+            ">> {}\n{}\n{}{:.3} ms",
+            input,
+            outcome,
+            NOTE,
+            elapsed.as_secs_f64() * 1000.0
+        )
+        .map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+}
+
+/// Re-run the inputs of the transcript at `path` and compare the results,
+/// returning how many inputs were replayed or the first difference.
+pub fn replay(path: &Path, config: &Config) -> Result<usize, String> {
+    let recorded =
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let expected: String = recorded
+        .lines()
+        .filter(|line| !line.starts_with(NOTE))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    let inputs: Vec<&str> = expected
+        .lines()
+        .filter_map(|line| line.strip_prefix(">> "))
+        .collect();
+    let actual = transcript(&inputs.join("\n"), config);
+    match first_difference(&expected, &actual) {
+        None => Ok(inputs.len()),
+        Some((line, e, a)) => Err(format!(
+            "{}: replay differs at transcript line {} (not counting timings)\n  expected: {}\n    actual: {}",
+            path.display(),
+            line,
+            e.unwrap_or("<end of transcript>"),
+            a.unwrap_or("<end of output>")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_then_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.txt");
+
+        let mut recorder = Recorder::create(&path).unwrap();
+        let elapsed = Duration::from_micros(250);
+        recorder.entry("1+2", &Ok("3".into()), elapsed).unwrap();
+        recorder
+            .entry("  ", &Err("ignored".into()), elapsed)
+            .unwrap();
+        let err = "Division by zero\n  1/0\n    ^".to_string();
+        recorder.entry("1/0", &Err(err), elapsed).unwrap();
+        drop(recorder);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            text,
+            ">> 1+2\n=> 3\n# 0.250 ms\n>> 1/0\nError: Division by zero\n  1/0\n    ^\n# 0.250 ms\n"
+        );
+        assert_eq!(replay(&path, &Config::default()), Ok(2));
+
+        std::fs::write(&path, text.replace("=> 3", "=> 4")).unwrap();
+        let diff = replay(&path, &Config::default()).unwrap_err();
+        assert!(
+            diff.ends_with("line 2 (not counting timings)\n  expected: => 4\n    actual: => 3"),
+            "{diff}"
+        );
+    }
+}
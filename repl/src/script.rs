@@ -0,0 +1,431 @@
+//! Running Q scripts: one expression per line, or literate Markdown
+//! whose fenced `q` code blocks hold the expressions.
+
+use std::fmt;
+use std::path::Path;
+
+use bumpalo::Bump;
+use qparser::parser::{self, blank_comments};
+use qparser::{Config, eval_str_in};
+
+/// Whether a script line could not be parsed or failed to evaluate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    Parse,
+    Eval,
+}
+
+/// An error reported for one line of a script.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    /// 1-based line number
+    pub line: usize,
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: Error: {}", self.line, self.message)
+    }
+}
+
+/// How a script's source is laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One expression per line
+    Plain,
+    /// Markdown; only lines inside fenced code blocks tagged `q` are run
+    Markdown,
+}
+
+impl Format {
+    /// `Markdown` for `.md` files, `Plain` for anything else.
+    pub fn of(path: &Path) -> Format {
+        match path.extension() {
+            Some(ext) if ext == "md" => Format::Markdown,
+            _ => Format::Plain,
+        }
+    }
+}
+
+/// Role of one line of a Markdown script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fence {
+    /// Prose, or code in another language
+    Text,
+    /// A fence opening a `q` block
+    Open,
+    /// A line of code inside a `q` block
+    Code,
+    /// The fence closing a `q` block
+    Close,
+}
+
+/// Classify each line of a Markdown document. A `q` block opens with a
+/// ```` ``` ```` or `~~~` fence whose info string starts with `q` and
+/// closes at the next fence of the same kind.
+fn fences(source: &str) -> Vec<Fence> {
+    let mut open: Option<(&str, bool)> = None;
+    source
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            let marker = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m));
+            match (open, marker) {
+                (None, Some(m)) => {
+                    let is_q = trimmed[3..].split_whitespace().next() == Some("q");
+                    open = Some((m, is_q));
+                    if is_q { Fence::Open } else { Fence::Text }
+                }
+                (Some((m, is_q)), Some(found)) if found == m && trimmed[3..].trim().is_empty() => {
+                    open = None;
+                    if is_q { Fence::Close } else { Fence::Text }
+                }
+                (Some((_, true)), _) => Fence::Code,
+                _ => Fence::Text,
+            }
+        })
+        .collect()
+}
+
+/// The 1-based line number and text of each line of `source` to evaluate.
+fn code_lines(source: &str, format: Format) -> Vec<(usize, &str)> {
+    let lines = source.lines().enumerate();
+    let code: Vec<(usize, &str)> = match format {
+        // A leading `#!` line lets plain scripts be made executable
+        Format::Plain => lines
+            .filter(|(i, line)| !(*i == 0 && line.starts_with("#!")))
+            .collect(),
+        Format::Markdown => lines
+            .zip(fences(source))
+            .filter(|(_, fence)| *fence == Fence::Code)
+            .map(|(line, _)| line)
+            .collect(),
+    };
+    code.into_iter()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| (i + 1, line))
+        .collect()
+}
+
+/// Evaluate each non-blank line of `source`, passing each result and its
+/// line number to `emit`.
+/// A `#!` interpreter line at the very start of a plain script is skipped,
+/// so scripts can be made executable. Block comments in a plain script may
+/// span lines; one left open is the only diagnostic and nothing is run.
+/// Returns the diagnostics for lines that failed, in line order.
+pub fn run_source(
+    source: &str,
+    format: Format,
+    arena: &mut Bump,
+    config: &Config,
+    mut emit: impl FnMut(usize, &str),
+) -> Vec<Diagnostic> {
+    let blanked;
+    let source = match format {
+        Format::Plain => match blank_comments(source) {
+            Ok(code) => {
+                blanked = code;
+                blanked.as_str()
+            }
+            Err(span) => {
+                let line = source
+                    .chars()
+                    .take(span.start)
+                    .filter(|&c| c == '\n')
+                    .count()
+                    + 1;
+                return vec![Diagnostic {
+                    line,
+                    kind: ErrorKind::Parse,
+                    message: "Unterminated block comment".to_string(),
+                }];
+            }
+        },
+        Format::Markdown => source,
+    };
+    let mut diagnostics = Vec::new();
+    for (line, code) in code_lines(source, format) {
+        match eval_str_in(arena, code, config) {
+            Ok(val) => emit(line, &val),
+            Err(message) => {
+                // Errors are strings; parsing again tells the kinds apart
+                let kind = match parser::parse_expr(arena, code, config) {
+                    Ok(_) => ErrorKind::Eval,
+                    Err(_) => ErrorKind::Parse,
+                };
+                diagnostics.push(Diagnostic {
+                    line,
+                    kind,
+                    message,
+                })
+            }
+        }
+        arena.reset();
+    }
+    diagnostics
+}
+
+/// Re-render a Markdown script with the results of each `q` block in a
+/// `text` block right after it. Errors are shown in place of results.
+pub fn render_markdown(source: &str, arena: &mut Bump, config: &Config) -> String {
+    let mut out = String::new();
+    let mut results = Vec::new();
+    for (line, fence) in source.lines().zip(fences(source)) {
+        out.push_str(line);
+        out.push('\n');
+        match fence {
+            Fence::Code if !line.trim().is_empty() => {
+                results.push(match eval_str_in(arena, line, config) {
+                    Ok(val) => val,
+                    Err(message) => format!("Error: {}", message),
+                });
+                arena.reset();
+            }
+            Fence::Close if !results.is_empty() => {
+                out.push_str("\n```text\n");
+                for result in results.drain(..) {
+                    out.push_str(&result);
+                    out.push('\n');
+                }
+                out.push_str("```\n");
+            }
+            _ => (),
+        }
+    }
+    out
+}
+
+/// Render a script's results and diagnostics for other programs: one
+/// tab-separated record per evaluated line, in line order, either
+/// `LINE\tok\tVALUE` or `LINE\terror\tMESSAGE`. Only the first line of an
+/// error message is kept.
+pub fn render_porcelain(results: &[(usize, String)], diagnostics: &[Diagnostic]) -> Vec<String> {
+    let mut records: Vec<(usize, String)> = results
+        .iter()
+        .map(|(line, value)| (*line, format!("{}\tok\t{}", line, value)))
+        .chain(diagnostics.iter().map(|d| {
+            let message = d.message.lines().next().unwrap_or_default();
+            (d.line, format!("{}\terror\t{}", d.line, message))
+        }))
+        .collect();
+    records.sort_by_key(|(line, _)| *line);
+    records.into_iter().map(|(_, record)| record).collect()
+}
+
+/// Render the diagnostics of the script `name` for display.
+///
+/// Diagnostics with the same headline, the first line of the message,
+/// are merged into one entry (at the first line it occurred on, with that
+/// line's source excerpt) with an occurrence count. With `max` set,
+/// entries past the cap are dropped and a footer counts the suppressed
+/// errors.
+pub fn render_diagnostics(
+    name: &str,
+    diagnostics: &[Diagnostic],
+    max: Option<usize>,
+) -> Vec<String> {
+    // (first diagnostic, occurrences) in order of first appearance
+    let mut groups: Vec<(&Diagnostic, usize)> = Vec::new();
+    for d in diagnostics {
+        match groups
+            .iter_mut()
+            .find(|(g, _)| g.message.lines().next() == d.message.lines().next())
+        {
+            Some((_, count)) => *count += 1,
+            None => groups.push((d, 1)),
+        }
+    }
+    let shown = max.unwrap_or(groups.len()).min(groups.len());
+    let mut lines: Vec<String> = groups[..shown]
+        .iter()
+        .map(|(d, count)| match count {
+            1 => format!("{}: {}", name, d),
+            // Keep the count on the message line, above any source excerpt
+            n => {
+                let text = format!("{}: {}", name, d);
+                match text.split_once('\n') {
+                    Some((head, excerpt)) => format!("{} ({} occurrences)\n{}", head, n, excerpt),
+                    None => format!("{} ({} occurrences)", text, n),
+                }
+            }
+        })
+        .collect();
+    let suppressed: usize = groups[shown..].iter().map(|(_, count)| count).sum();
+    if suppressed > 0 {
+        lines.push(format!("{}: {} more errors suppressed", name, suppressed));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_source_reports_failing_lines() {
+        let mut values = Vec::new();
+        let diags = run_source(
+            "1+2\n\n1/0\n2*3\n",
+            Format::Plain,
+            &mut Bump::new(),
+            &Config::default(),
+            |_, v| values.push(v.to_string()),
+        );
+        assert_eq!(values, ["3", "6"]);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].to_string(),
+            "line 3: Error: Division by zero\n  1/0\n    ^"
+        );
+    }
+
+    #[test]
+    fn test_porcelain_records_and_error_kinds() {
+        let mut results = Vec::new();
+        let diags = run_source(
+            "1+2\n1/0\n\n3 4\n",
+            Format::Plain,
+            &mut Bump::new(),
+            &Config::default(),
+            |line, v| results.push((line, v.to_string())),
+        );
+        let kinds: Vec<ErrorKind> = diags.iter().map(|d| d.kind).collect();
+        assert_eq!(kinds, [ErrorKind::Eval, ErrorKind::Parse]);
+        assert_eq!(
+            render_porcelain(&results, &diags),
+            [
+                "1\tok\t3",
+                "2\terror\tDivision by zero",
+                "4\terror\tfound '4' but one of &, *, +, -, /, <, =, >, |, end of input was expected"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_source_skips_shebang() {
+        let mut values = Vec::new();
+        let diags = run_source(
+            "#!/usr/bin/env repl
+1+1
+",
+            Format::Plain,
+            &mut Bump::new(),
+            &Config::default(),
+            |_, v| values.push(v.to_string()),
+        );
+        assert!(diags.is_empty());
+        assert_eq!(values, ["2"]);
+    }
+
+    #[test]
+    fn test_run_source_skips_block_comments() {
+        let mut values = Vec::new();
+        let diags = run_source(
+            "1+1 {- two -}\n{- not run:\n1/0\n{- nested -} -}\n2*3\n",
+            Format::Plain,
+            &mut Bump::new(),
+            &Config::default(),
+            |_, v| values.push(v.to_string()),
+        );
+        assert!(diags.is_empty(), "{diags:?}");
+        assert_eq!(values, ["2", "6"]);
+
+        let diags = run_source(
+            "1+1\n2 {- open\n3\n",
+            Format::Plain,
+            &mut Bump::new(),
+            &Config::default(),
+            |_, _| (),
+        );
+        assert_eq!(
+            diags,
+            [Diagnostic {
+                line: 2,
+                kind: ErrorKind::Parse,
+                message: "Unterminated block comment".to_string()
+            }]
+        );
+    }
+
+    const NOTEBOOK: &str = "# Notes
+
+```q
+1+2
+```
+
+Not run:
+
+```sh
+1/0
+```
+
+~~~q
+2*3
+1/0
+~~~
+";
+
+    #[test]
+    fn test_run_markdown_runs_only_q_blocks() {
+        let mut values = Vec::new();
+        let diags = run_source(
+            NOTEBOOK,
+            Format::Markdown,
+            &mut Bump::new(),
+            &Config::default(),
+            |_, v| values.push(v.to_string()),
+        );
+        assert_eq!(values, ["3", "6"]);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].line, 15);
+        assert_eq!(Format::of(Path::new("notes.q.md")), Format::Markdown);
+    }
+
+    #[test]
+    fn test_render_markdown_inserts_results() {
+        let doc = render_markdown(NOTEBOOK, &mut Bump::new(), &Config::default());
+        assert!(doc.starts_with("# Notes\n\n```q\n1+2\n```\n\n```text\n3\n```\n"));
+        assert!(doc.ends_with("~~~\n\n```text\n6\nError: Division by zero\n  1/0\n    ^\n```\n"));
+    }
+
+    #[test]
+    fn test_render_diagnostics_dedupes_and_caps() {
+        let diag = |line: usize, message: &str| Diagnostic {
+            line,
+            kind: ErrorKind::Eval,
+            message: message.to_string(),
+        };
+        let diags = [
+            diag(1, "Division by zero"),
+            diag(2, "Type error in addition"),
+            diag(3, "Division by zero"),
+            diag(4, "Too deep"),
+            diag(5, "Too deep"),
+        ];
+        assert_eq!(
+            render_diagnostics("f.q", &diags, Some(1)),
+            [
+                "f.q: line 1: Error: Division by zero (2 occurrences)",
+                "f.q: 3 more errors suppressed",
+            ]
+        );
+        assert_eq!(render_diagnostics("f.q", &diags, None).len(), 3);
+    }
+
+    #[test]
+    fn test_render_diagnostics_merges_errors_with_different_excerpts() {
+        let diags = run_source(
+            "1/0\n2/0\n3/0\n",
+            Format::Plain,
+            &mut Bump::new(),
+            &Config::default(),
+            |_, _| (),
+        );
+        assert_eq!(
+            render_diagnostics("f.q", &diags, None),
+            ["f.q: line 1: Error: Division by zero (3 occurrences)\n  1/0\n    ^"]
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! Per-input cost reports for `:stats on`.
+//!
+//! Allocations are counted by a wrapper around the system allocator that
+//! is only installed when the crate is built with the `alloc-stats`
+//! feature, so normal builds pay nothing for it.
+
+use std::fmt;
+use std::time::Duration;
+
+#[cfg(feature = "alloc-stats")]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    pub static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+    pub static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+    /// The system allocator, counting allocations and requested bytes.
+    pub struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            BYTES.fetch_add(new_size, Ordering::Relaxed);
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: Counting = Counting;
+}
+
+/// Allocations and bytes allocated so far, or `None` if not counting.
+pub fn allocations() -> Option<(usize, usize)> {
+    #[cfg(feature = "alloc-stats")]
+    {
+        use std::sync::atomic::Ordering;
+        Some((
+            counting::ALLOCATIONS.load(Ordering::Relaxed),
+            counting::BYTES.load(Ordering::Relaxed),
+        ))
+    }
+    #[cfg(not(feature = "alloc-stats"))]
+    None
+}
+
+/// What evaluating one input cost.
+pub struct Report {
+    pub elapsed: Duration,
+    /// Expression nodes evaluated
+    pub steps: usize,
+    /// Deepest expression level reached
+    pub depth: usize,
+    /// Allocations and bytes, when counted
+    pub allocations: Option<(usize, usize)>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "time {:.3} ms, {} step{}, depth {}, ",
+            self.elapsed.as_secs_f64() * 1000.0,
+            self.steps,
+            if self.steps == 1 { "" } else { "s" },
+            self.depth
+        )?;
+        match self.allocations {
+            Some((count, bytes)) => write!(f, "{} allocations ({} B)", count, bytes),
+            None => write!(
+                f,
+                "allocations not counted (build with --features alloc-stats)"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_format() {
+        let report = Report {
+            elapsed: Duration::from_micros(1500),
+            steps: 5,
+            depth: 3,
+            allocations: Some((2, 96)),
+        };
+        assert_eq!(
+            report.to_string(),
+            "time 1.500 ms, 5 steps, depth 3, 2 allocations (96 B)"
+        );
+        let report = Report {
+            steps: 1,
+            allocations: None,
+            ..report
+        };
+        assert_eq!(
+            report.to_string(),
+            "time 1.500 ms, 1 step, depth 3, allocations not counted (build with --features alloc-stats)"
+        );
+    }
+}
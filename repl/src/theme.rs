@@ -0,0 +1,96 @@
+//! Terminal styling for REPL output.
+//!
+//! All colors live here so that `--color`, `--theme` and the `NO_COLOR`
+//! convention (<https://no-color.org>) are honored in one place.
+
+use std::io::IsTerminal;
+
+/// When to emit ANSI colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal and `NO_COLOR` is unset
+    Auto,
+    Always,
+    Never,
+}
+
+/// Named color palettes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ThemeName {
+    Default,
+    /// Bold, bright colors for low-contrast terminals
+    HighContrast,
+}
+
+/// Kinds of output that can be styled.
+#[derive(Clone, Copy, Debug)]
+pub enum Style {
+    /// Evaluation results (stdout)
+    Value,
+    /// Error messages (stderr)
+    Error,
+}
+
+/// Resolved styling for the current session.
+pub struct Theme {
+    stdout: bool,
+    stderr: bool,
+    value: &'static str,
+    error: &'static str,
+}
+
+const RESET: &str = "\x1b[0m";
+
+/// Decide whether to color a stream.
+fn enabled(choice: ColorChoice, is_tty: bool, no_color: Option<&str>) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => is_tty && no_color.is_none_or(str::is_empty),
+    }
+}
+
+impl Theme {
+    /// Build a theme from the CLI choice, the environment and the attached streams.
+    pub fn new(choice: ColorChoice, name: ThemeName) -> Self {
+        let no_color = std::env::var("NO_COLOR").ok();
+        let (value, error) = match name {
+            ThemeName::Default => ("\x1b[32m", "\x1b[31m"),
+            ThemeName::HighContrast => ("\x1b[1;97m", "\x1b[1;91m"),
+        };
+        Theme {
+            stdout: enabled(choice, std::io::stdout().is_terminal(), no_color.as_deref()),
+            stderr: enabled(choice, std::io::stderr().is_terminal(), no_color.as_deref()),
+            value,
+            error,
+        }
+    }
+
+    /// Wrap `text` in the escape codes for `style`, if coloring its stream.
+    pub fn paint(&self, style: Style, text: &str) -> String {
+        let (on, code) = match style {
+            Style::Value => (self.stdout, self.value),
+            Style::Error => (self.stderr, self.error),
+        };
+        if on {
+            format!("{}{}{}", code, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_color_disables_auto_only() {
+        assert!(enabled(ColorChoice::Auto, true, None));
+        assert!(enabled(ColorChoice::Auto, true, Some("")));
+        assert!(!enabled(ColorChoice::Auto, true, Some("1")));
+        assert!(!enabled(ColorChoice::Auto, false, None));
+        assert!(enabled(ColorChoice::Always, false, Some("1")));
+        assert!(!enabled(ColorChoice::Never, true, None));
+    }
+}
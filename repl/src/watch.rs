@@ -0,0 +1,86 @@
+//! `--watch`: re-run a script whenever it changes on disk.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use bumpalo::Bump;
+use notify::{RecursiveMode, Watcher};
+use qparser::Config;
+
+use crate::script::{Diagnostic, Format, render_diagnostics, run_source};
+use crate::theme::{Style, Theme};
+
+/// How long to wait for an editor's burst of write events to settle.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Run `path`, then re-run it on every change until interrupted.
+///
+/// The arena and config are kept across runs. The first run prints its
+/// diagnostics (deduplicated and capped at `max_diagnostics`); later runs
+/// print only the change: `+` for new errors, `-` for fixed ones.
+pub fn watch(
+    path: &Path,
+    config: &Config,
+    theme: &Theme,
+    max_diagnostics: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    // Watch the parent directory: editors often replace the file rather than write it
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    let name = path.file_name();
+
+    let mut arena = Bump::new();
+    let mut previous: Option<Vec<Diagnostic>> = None;
+    loop {
+        let source = std::fs::read_to_string(path).unwrap_or_default();
+        let diagnostics = run_source(&source, Format::of(path), &mut arena, config, |_, val| {
+            println!("{}", val)
+        });
+        match &previous {
+            None => {
+                let name = path.display().to_string();
+                for line in render_diagnostics(&name, &diagnostics, max_diagnostics) {
+                    eprintln!("{}", theme.paint(Style::Error, &line));
+                }
+            }
+            Some(prev) => report_diff(path, prev, &diagnostics, theme),
+        }
+        previous = Some(diagnostics);
+        eprintln!("[watching {}]", path.display());
+
+        // Block until an event touches our file, then drain the burst
+        loop {
+            let event = rx.recv()??;
+            if event.paths.iter().any(|p| p.file_name() == name) {
+                break;
+            }
+        }
+        while rx.recv_timeout(DEBOUNCE).is_ok() {}
+    }
+}
+
+/// Print which diagnostics appeared and disappeared between two runs.
+fn report_diff(path: &Path, prev: &[Diagnostic], next: &[Diagnostic], theme: &Theme) {
+    let before: HashSet<&Diagnostic> = prev.iter().collect();
+    let after: HashSet<&Diagnostic> = next.iter().collect();
+    let mut changed = false;
+    for d in prev.iter().filter(|d| !after.contains(d)) {
+        eprintln!("- {}: {}", path.display(), d);
+        changed = true;
+    }
+    for d in next.iter().filter(|d| !before.contains(d)) {
+        let line = format!("+ {}: {}", path.display(), d);
+        eprintln!("{}", theme.paint(Style::Error, &line));
+        changed = true;
+    }
+    if !changed {
+        eprintln!("diagnostics unchanged ({})", next.len());
+    }
+}
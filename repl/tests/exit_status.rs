@@ -0,0 +1,37 @@
+use std::process::Command;
+
+fn repl(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_repl"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_unreadable_script_exits_with_usage_status() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.q");
+    let out = repl(&[missing.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(3));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.starts_with(&format!("repl: {}: ", missing.display())),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("Os {"), "{stderr}");
+
+    // A directory cannot be read as a script either
+    let out = repl(&[dir.path().to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(3));
+}
+
+#[test]
+fn test_script_errors_keep_their_statuses() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("s.q");
+    std::fs::write(&script, "1/0\n").unwrap();
+    assert_eq!(repl(&[script.to_str().unwrap()]).status.code(), Some(1));
+    std::fs::write(&script, "1+\n").unwrap();
+    assert_eq!(repl(&[script.to_str().unwrap()]).status.code(), Some(2));
+    assert_eq!(repl(&["--bogus"]).status.code(), Some(3));
+}